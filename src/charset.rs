@@ -0,0 +1,133 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Character encodings of dictionary files: the UTF-8 files of bedic_data-utf8 and
+//! the original Windows-1251 (or KOI8-R) files of kbedic.
+
+use std::fmt;
+use std::str::{self, FromStr};
+
+use encoding::all::{KOI8_R, WINDOWS_1251};
+use encoding::{DecoderTrap, Encoding};
+
+/// Most frequent Bulgarian letters, used to tell Windows-1251 from KOI8-R.
+/// Both cases count, as the Bulgarian headwords of bg_en are uppercase.
+const FREQUENT_LETTERS: &str = "аеинортАЕИНОРТ";
+
+/// Character encoding of a dictionary file
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Charset {
+    Utf8,
+    /// Windows-1251, used by the original kbedic files
+    Cp1251,
+    Koi8R,
+}
+
+impl Charset {
+    /// Identifier accepted by `from_str()`
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Charset::Utf8 => "utf-8",
+            Charset::Cp1251 => "cp1251",
+            Charset::Koi8R => "koi8-r",
+        }
+    }
+
+    /// Guess the encoding of `data`: valid UTF-8 is taken as UTF-8,
+    /// otherwise the single-byte encoding which yields more common Bulgarian letters wins.
+    pub fn detect(data: &[u8]) -> Charset {
+        if str::from_utf8(data).is_ok() {
+            return Charset::Utf8;
+        }
+        let score = |charset: Charset| {
+            decode(data, charset)
+                .chars()
+                .filter(|c| FREQUENT_LETTERS.contains(*c))
+                .count()
+        };
+        let charset = if score(Charset::Koi8R) > score(Charset::Cp1251) {
+            Charset::Koi8R
+        } else {
+            Charset::Cp1251
+        };
+        debug!("Detected encoding {}", charset);
+        charset
+    }
+}
+
+impl fmt::Display for Charset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Charset, String> {
+        match s.to_lowercase().as_str() {
+            "utf-8" | "utf8" => Ok(Charset::Utf8),
+            "cp1251" | "windows-1251" => Ok(Charset::Cp1251),
+            "koi8-r" | "koi8r" => Ok(Charset::Koi8R),
+            _ => Err(format!(
+                "Unknown encoding {:?}, expected utf-8, cp1251 or koi8-r",
+                s
+            )),
+        }
+    }
+}
+
+/// Decode `data` from `charset`. Invalid bytes are replaced with U+FFFD.
+pub fn decode(data: &[u8], charset: Charset) -> String {
+    let decoded = match charset {
+        Charset::Utf8 => return String::from_utf8_lossy(data).into_owned(),
+        Charset::Cp1251 => WINDOWS_1251.decode(data, DecoderTrap::Replace),
+        Charset::Koi8R => KOI8_R.decode(data, DecoderTrap::Replace),
+    };
+    // Replace trap never fails
+    decoded.unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding::EncoderTrap;
+
+    const TEXT: &str = "КНИГА\nж book; тетрадка\n^;";
+
+    #[test]
+    fn detected_encodings() {
+        let cp1251 = WINDOWS_1251.encode(TEXT, EncoderTrap::Strict).unwrap();
+        let koi8_r = KOI8_R.encode(TEXT, EncoderTrap::Strict).unwrap();
+        assert_eq!(Charset::detect(TEXT.as_bytes()), Charset::Utf8);
+        assert_eq!(Charset::detect(&cp1251), Charset::Cp1251);
+        assert_eq!(Charset::detect(&koi8_r), Charset::Koi8R);
+        // ASCII is valid UTF-8
+        assert_eq!(Charset::detect(b"BOOK\nn\n^;"), Charset::Utf8);
+    }
+
+    #[test]
+    fn decoded_text() {
+        let cp1251 = WINDOWS_1251.encode(TEXT, EncoderTrap::Strict).unwrap();
+        let koi8_r = KOI8_R.encode(TEXT, EncoderTrap::Strict).unwrap();
+        assert_eq!(decode(TEXT.as_bytes(), Charset::Utf8), TEXT);
+        assert_eq!(decode(&cp1251, Charset::Cp1251), TEXT);
+        assert_eq!(decode(&koi8_r, Charset::Koi8R), TEXT);
+        // CP1251 is not valid UTF-8
+        assert!(decode(&cp1251, Charset::Utf8).contains('\u{FFFD}'));
+    }
+
+    #[test]
+    fn charset_names() {
+        for &charset in &[Charset::Utf8, Charset::Cp1251, Charset::Koi8R] {
+            assert_eq!(charset.id().parse::<Charset>(), Ok(charset));
+        }
+        assert_eq!("Windows-1251".parse::<Charset>(), Ok(Charset::Cp1251));
+        assert!("latin1".parse::<Charset>().is_err());
+    }
+}
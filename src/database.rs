@@ -7,79 +7,767 @@
 // except according to those terms.
 
 use std::io::prelude::*;
+use std::io::{self, ErrorKind};
+use std::env;
+use std::error::Error;
+use std::fmt;
 use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
 use regex::Regex;
-use std::cmp::Ordering::{self, Equal, Greater, Less};
+use std::cmp::Ordering;
+use std::ops::Range;
+use std::sync::{Arc, OnceLock};
+use dirs;
+use fulltext::{self, InvertedIndex};
+use fuzzy::{self, BkTree};
+use index::{self, CompiledIndex};
+use charset::{self, Charset};
+use history::{HistoryFile, HistoryRecord, ENTRY_DELIMITER};
 //use log;
 
-const PREFIX_NUMBER: usize = 100;
+const EN_BG_FILE: &str = "en_bg-utf8.dat";
+const BG_EN_FILE: &str = "bg_en-utf8.dat";
+/// Names of the original kbedic files, usually encoded in Windows-1251
+const EN_BG_KBEDIC_FILE: &str = "en_bg.dat";
+const BG_EN_KBEDIC_FILE: &str = "bg_en.dat";
+const LEGACY_DATA_DIR: &str = "/usr/local/share/bedic";
+const DEFAULT_XDG_DATA_DIRS: &str = "/usr/local/share/:/usr/share/";
 
+/// List the directories searched for dictionary files, in order of priority:
+/// `data_dir` (the `--data-dir` option), `$RBEDIC_DATA_DIR`, `$XDG_DATA_HOME/bedic`,
+/// `$XDG_DATA_DIRS/*/bedic` and finally the legacy `/usr/local/share/bedic`.
+pub fn data_dirs(data_dir: Option<&str>) -> Vec<PathBuf> {
+    let mut search_path: Vec<PathBuf> = Vec::new();
+    if let Some(dir) = data_dir {
+        search_path.push(PathBuf::from(dir));
+    }
+    if let Some(dir) = env::var_os("RBEDIC_DATA_DIR") {
+        if !dir.is_empty() {
+            search_path.push(PathBuf::from(dir));
+        }
+    }
+    match env::var_os("XDG_DATA_HOME") {
+        Some(ref dir) if !dir.is_empty() => search_path.push(PathBuf::from(dir).join("bedic")),
+        _ => if let Some(home_dir) = dirs::home_dir() {
+            search_path.push(home_dir.join(".local/share/bedic"));
+        },
+    }
+    let xdg_data_dirs = match env::var_os("XDG_DATA_DIRS") {
+        Some(ref data_dirs) if !data_dirs.is_empty() => data_dirs.clone(),
+        _ => DEFAULT_XDG_DATA_DIRS.into(),
+    };
+    for dir in env::split_paths(&xdg_data_dirs) {
+        search_path.push(dir.join("bedic"));
+    }
+    let legacy = PathBuf::from(LEGACY_DATA_DIR);
+    if !search_path.contains(&legacy) {
+        search_path.push(legacy);
+    }
+    search_path
+}
+
+/// Return the first directory from `data_dirs()` which contains both dictionary files,
+/// either the UTF-8 files of bedic_data-utf8 or the original files of kbedic.
+pub fn find_data_dir(data_dir: Option<&str>) -> Option<PathBuf> {
+    data_dirs(data_dir).into_iter().find(|dir| {
+        trace!("Looking for dictionaries into {:?}", dir);
+        dict_file(dir, Direction::EnBg).is_file() && dict_file(dir, Direction::BgEn).is_file()
+    })
+}
+
+/// Names of the dictionary files of `direction`, in order of preference
+fn dict_file_names(direction: Direction) -> [&'static str; 2] {
+    match direction {
+        Direction::EnBg => [EN_BG_FILE, EN_BG_KBEDIC_FILE],
+        Direction::BgEn => [BG_EN_FILE, BG_EN_KBEDIC_FILE],
+    }
+}
+
+/// Dictionary file of `direction` into `dict_dir`: the first existing one
+/// from `dict_file_names()`, or the UTF-8 one if none exists
+fn dict_file(dict_dir: &Path, direction: Direction) -> PathBuf {
+    let file_names = dict_file_names(direction);
+    file_names
+        .iter()
+        .map(|file_name| dict_dir.join(file_name))
+        .find(|path| path.is_file())
+        .unwrap_or_else(|| dict_dir.join(file_names[0]))
+}
+
+/// Translation direction of a dictionary
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// English-Bulgarian, `en_bg-utf8.dat`
+    EnBg,
+    /// Bulgarian-English, `bg_en-utf8.dat`
+    BgEn,
+}
+
+impl Direction {
+    /// Identifier of the direction, as used into file names
+    pub fn id(&self) -> &'static str {
+        match *self {
+            Direction::EnBg => "en_bg",
+            Direction::BgEn => "bg_en",
+        }
+    }
+
+    /// Short label shown to the user
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Direction::EnBg => "EN→BG",
+            Direction::BgEn => "BG→EN",
+        }
+    }
+
+    pub fn opposite(&self) -> Direction {
+        match *self {
+            Direction::EnBg => Direction::BgEn,
+            Direction::BgEn => Direction::EnBg,
+        }
+    }
+
+    /// Guess the direction by the script of `word`:
+    /// words containing Cyrillic letters are Bulgarian.
+    pub fn detect(word: &str) -> Direction {
+        if word.chars().any(is_cyrillic) {
+            Direction::BgEn
+        } else {
+            Direction::EnBg
+        }
+    }
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    /// Accepts `en_bg`, `en-bg`, `bg_en` and `bg-en` in any case
+    fn from_str(s: &str) -> Result<Direction, String> {
+        match s.to_lowercase().replace('-', "_").as_str() {
+            "en_bg" => Ok(Direction::EnBg),
+            "bg_en" => Ok(Direction::BgEn),
+            _ => Err(format!("Unknown direction {:?}, expected en-bg or bg-en", s)),
+        }
+    }
+}
+
+/// Return true for letters of the Cyrillic Unicode block
+pub fn is_cyrillic(c: char) -> bool {
+    ('\u{0400}'..='\u{04FF}').contains(&c)
+}
+
+/// A single dictionary entry.
+///
+/// Entries are ordered and compared by `word` only.
 #[derive(Clone, Debug, Eq)]
 pub struct DictDB {
+    /// The headword, as written into the first line of the entry
     pub word: String,
+    /// The whole text of the entry, including the headword line
     pub translation: String,
+    /// The dictionary which the entry comes from
+    pub direction: Direction,
 }
 
-impl DictDB {
-    /// Load database files into memory
-    pub fn new() -> Vec<DictDB> {
-        // read from files
-        let mut string_en_bg = String::new();
-        let mut string_bg_en = String::new();
-        // TODO: Add Windows support
-        {
-            info!("Loading dictionaries");
-            let mut file_en_bg = File::open("/usr/local/share/bedic/en_bg-utf8.dat")
-                .expect("Unable to open file en_bg-utf8.dat");
-            let mut file_bg_en = File::open("/usr/local/share/bedic/bg_en-utf8.dat")
-                .expect("Unable to open file bg_en-utf8.dat");
-
-            file_en_bg
-                .read_to_string(&mut string_en_bg)
-                .expect("Unable to read file en_bg-utf8.dat");
-            file_bg_en
-                .read_to_string(&mut string_bg_en)
-                .expect("Unable to read file bg_en-utf8.dat");
+/// An entry found by `fuzzy_search()`
+#[derive(Clone, Debug)]
+pub struct FuzzyMatch {
+    /// Edit distance between the searched text and the word
+    pub distance: usize,
+    pub entry: DictDB,
+}
+
+/// An entry found by `fulltext_search()`
+#[derive(Clone, Debug)]
+pub struct FullTextMatch {
+    /// Part of the translation around the searched text
+    pub snippet: String,
+    pub entry: DictDB,
+}
+
+/// Number of parsed entries between two progress reports of `Dictionaries::load()`
+const PROGRESS_STEP: usize = 5000;
+
+/// Progress of `Dictionaries::load()`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LoadProgress {
+    /// Number of dictionary files loaded so far
+    pub files_loaded: usize,
+    /// Number of dictionary files to load
+    pub files_total: usize,
+    /// Number of entries parsed so far, from all files
+    pub entries_parsed: usize,
+}
+
+/// A source of dictionary entries: a bedic `.dat` file, a StarDict dictionary, ...
+pub trait DictionarySource {
+    /// Name of the dictionary shown to the user
+    fn name(&self) -> String;
+
+    /// Translation direction, for the sources of the bedic dictionaries
+    fn direction(&self) -> Option<Direction> {
+        None
+    }
+
+    /// Read all entries, in any order. `progress` gets the number of entries read so far.
+    fn read(&self, progress: &mut dyn FnMut(usize)) -> Result<Vec<DictDB>, DictError>;
+}
+
+/// A bedic `.dat` file, with entries delimited by `^;`
+#[derive(Clone, Debug)]
+pub struct BedicFile {
+    pub direction: Direction,
+    pub path: PathBuf,
+    /// Encoding of the file, detected if `None`
+    pub encoding: Option<Charset>,
+}
+
+impl DictionarySource for BedicFile {
+    fn name(&self) -> String {
+        self.direction.label().to_string()
+    }
+
+    fn direction(&self) -> Option<Direction> {
+        Some(self.direction)
+    }
+
+    fn read(&self, progress: &mut dyn FnMut(usize)) -> Result<Vec<DictDB>, DictError> {
+        let file_content = read_dict_file(&self.path, self.encoding)?;
+        let entries = parse_with_progress(&file_content.text, self.direction, progress);
+        if entries.is_empty() {
+            return Err(DictError::Parse(self.path.clone()));
         }
-        info!("Parse en_bg-utf8.dat");
-        let mut vector_en_bg = parse(&string_en_bg);
-        vector_en_bg.sort();
-        info!("Parse bg_en-utf8.dat");
-        let mut vector_bg_en = parse(&string_bg_en);
-        vector_bg_en.sort();
-
-        let concatenated_dictionaries = [&vector_en_bg[..], &vector_bg_en[..]].concat();
+        Ok(entries)
+    }
+}
+
+/// Sorted entries of a `Dictionary`
+#[derive(Clone, Debug)]
+enum Entries {
+    /// Parsed from the dictionary file
+    Parsed(Vec<DictDB>),
+    /// Read on demand from a compiled index, see `index`
+    Mapped(Arc<CompiledIndex>),
+}
+
+/// Sorted entries of one dictionary
+#[derive(Clone, Debug)]
+pub struct Dictionary {
+    /// Translation direction of the bedic dictionaries, `None` for the other ones
+    pub direction: Option<Direction>,
+    /// Name shown to the user
+    pub name: String,
+    entries: Entries,
+    /// Index of the words for `fuzzy_search()`, built by `build_search_indexes()`
+    /// or by the first search
+    fuzzy: OnceLock<BkTree>,
+    /// Index of the translations for `fulltext_search()`, built by
+    /// `build_search_indexes()` or by the first search
+    fulltext: OnceLock<InvertedIndex>,
+}
+
+impl Dictionary {
+    /// Parse the content of a bedic `.dat` file and sort the entries
+    pub fn new(direction: Direction, string_data: &str) -> Dictionary {
+        let mut entries = parse(string_data, direction);
+        entries.sort();
+        Dictionary::from_entries(direction, entries)
+    }
+
+    /// Dictionary of already sorted entries
+    pub fn from_entries(direction: Direction, entries: Vec<DictDB>) -> Dictionary {
+        Dictionary::build(Some(direction), direction.label().to_string(), Entries::Parsed(entries))
+    }
+
+    /// Dictionary reading its entries from `compiled_index`, without copying them
+    pub fn from_index(compiled_index: CompiledIndex) -> Dictionary {
+        let direction = compiled_index.direction();
+        Dictionary::build(
+            Some(direction),
+            direction.label().to_string(),
+            Entries::Mapped(Arc::new(compiled_index)),
+        )
+    }
+
+    /// Read the entries of `source` and sort them
+    pub fn from_source(
+        source: &dyn DictionarySource,
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<Dictionary, DictError> {
+        let mut entries = source.read(progress)?;
+        entries.sort();
+        Ok(Dictionary::build(source.direction(), source.name(), Entries::Parsed(entries)))
+    }
+
+    fn build(direction: Option<Direction>, name: String, entries: Entries) -> Dictionary {
+        Dictionary {
+            direction,
+            name,
+            entries,
+            fuzzy: OnceLock::new(),
+            fulltext: OnceLock::new(),
+        }
+    }
+
+    /// Dictionary without entries
+    pub fn empty(direction: Direction) -> Dictionary {
+        Dictionary::from_entries(direction, Vec::new())
+    }
+
+    /// Word of the entry at `index`
+    pub fn word(&self, index: usize) -> &str {
+        match self.entries {
+            Entries::Parsed(ref entries) => &entries[index].word,
+            Entries::Mapped(ref compiled_index) => compiled_index.word(index),
+        }
+    }
+
+    /// Translation of the entry at `index`
+    pub fn translation(&self, index: usize) -> &str {
+        match self.entries {
+            Entries::Parsed(ref entries) => &entries[index].translation,
+            Entries::Mapped(ref compiled_index) => compiled_index.translation(index),
+        }
+    }
+
+    /// Copy of the entry at `index`
+    pub fn entry(&self, index: usize) -> DictDB {
+        match self.entries {
+            Entries::Parsed(ref entries) => entries[index].clone(),
+            Entries::Mapped(ref compiled_index) => compiled_index.to_dict_db(index),
+        }
+    }
+
+    /// Copies of the entries of `range`
+    pub fn entries(&self, range: Range<usize>) -> Vec<DictDB> {
+        match self.entries {
+            Entries::Parsed(ref entries) => entries[range].to_vec(),
+            Entries::Mapped(ref compiled_index) => range.map(|index| compiled_index.to_dict_db(index)).collect(),
+        }
+    }
+
+    /// Build the indices of `fuzzy_search()` and `fulltext_search()` now rather than
+    /// by the first search, e.g. on the thread loading the dictionary
+    pub fn build_search_indexes(&self) {
+        self.fuzzy_index();
+        self.fulltext_index();
+    }
+
+    fn fuzzy_index(&self) -> &BkTree {
+        self.fuzzy.get_or_init(|| {
+            debug!("Index the words of {:?}", self.name);
+            BkTree::new(self.len(), |index| self.word(index))
+        })
+    }
+
+    fn fulltext_index(&self) -> &InvertedIndex {
+        self.fulltext.get_or_init(|| {
+            debug!("Index the translations of {:?}", self.name);
+            InvertedIndex::new((0..self.len()).map(|index| self.translation(index)))
+        })
+    }
+
+    /// Entries whose word matches `regex`, lazily in alphabetical order.
+    /// See `pattern::compile()` for wildcard and regular expression queries.
+    pub fn pattern_search<'a>(&'a self, regex: &'a Regex) -> impl Iterator<Item = DictDB> + 'a {
+        (0..self.len())
+            .filter(move |&index| regex.is_match(self.word(index)))
+            .map(move |index| self.entry(index))
+    }
+
+    /// Search for up to `limit` entries whose translation contains
+    /// all words of `searched_txt` (case insensitive), in alphabetical order.
+    /// The first search indexes the translations.
+    pub fn fulltext_search(&self, searched_txt: &str, limit: usize) -> Vec<FullTextMatch> {
+        self.fulltext_index()
+            .find(searched_txt)
+            .into_iter()
+            .take(limit)
+            .map(|index| FullTextMatch {
+                snippet: fulltext::snippet(self.translation(index), searched_txt),
+                entry: self.entry(index),
+            })
+            .collect()
+    }
+
+    /// Search for up to `limit` words similar to `searched_txt` (case insensitive),
+    /// the closest first. The first search indexes the words.
+    pub fn fuzzy_search(&self, searched_txt: &str, limit: usize) -> Vec<FuzzyMatch> {
+        let query = searched_txt.to_uppercase();
+        self.fuzzy_index()
+            .find(|index| self.word(index), &query, fuzzy::max_distance(&query))
+            .into_iter()
+            .take(limit)
+            .map(|(distance, index)| FuzzyMatch {
+                distance,
+                entry: self.entry(index),
+            })
+            .collect()
+    }
+
+    /// Search for words starting with `searched_txt` (case insensitive).
+    ///
+    /// Returns `Ok` when the exact word is found and `Err` otherwise.
+    /// Both variants hold all entries starting with `searched_txt`, in alphabetical order.
+    pub fn search(&self, searched_txt: &str) -> Result<Vec<DictDB>, Vec<DictDB>> {
+        let range = self.prefix_range(searched_txt);
+        let exact_match = range.start < range.end && self.word(range.start) == searched_txt.to_uppercase();
+        let entries = self.entries(range);
+        if exact_match {
+            Ok(entries)
+        } else {
+            Err(entries)
+        }
+    }
+
+    /// Range of the entries with words starting with `searched_txt` (case insensitive)
+    pub fn prefix_range(&self, searched_txt: &str) -> Range<usize> {
+        match self.entries {
+            Entries::Parsed(ref entries) => entries.my_prefix_range(&searched_txt.to_uppercase()),
+            Entries::Mapped(ref compiled_index) => compiled_index.prefix_range(searched_txt),
+        }
+    }
+
+    /// Return true if `searched_txt` is a headword (case insensitive)
+    pub fn contains(&self, searched_txt: &str) -> bool {
+        let range = self.prefix_range(searched_txt);
+        range.start < range.end && self.word(range.start) == searched_txt.to_uppercase()
+    }
+
+    pub fn len(&self) -> usize {
+        match self.entries {
+            Entries::Parsed(ref entries) => entries.len(),
+            Entries::Mapped(ref compiled_index) => compiled_index.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Identifies one dictionary of `Dictionaries`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DictKey {
+    /// A bedic dictionary
+    Bedic(Direction),
+    /// An extra dictionary, by its index into `Dictionaries::extra`
+    Extra(usize),
+}
+
+/// Both bedic dictionaries, kept separately by direction,
+/// and the extra dictionaries added from other sources
+#[derive(Clone, Debug)]
+pub struct Dictionaries {
+    pub en_bg: Dictionary,
+    pub bg_en: Dictionary,
+    /// Dictionaries added by `add_source()`, e.g. StarDict ones
+    pub extra: Vec<Dictionary>,
+}
+
+impl Default for Dictionaries {
+    fn default() -> Dictionaries {
+        Dictionaries {
+            en_bg: Dictionary::empty(Direction::EnBg),
+            bg_en: Dictionary::empty(Direction::BgEn),
+            extra: Vec::new(),
+        }
+    }
+}
+
+impl Dictionaries {
+    /// Load database files into memory.
+    /// The files are looked up into the directories returned by `data_dirs()`.
+    /// The encoding of the files is detected, see `Charset::detect()`.
+    pub fn new(data_dir: Option<&str>) -> Result<Dictionaries, DictError> {
+        Dictionaries::load(data_dir, None, &mut |_| ())
+    }
+
+    /// Load database files like `new()`, reporting the progress to `progress`
+    /// after each file and every few thousands of parsed entries.
+    /// When `encoding` is `None`, the encoding of the files is detected.
+    pub fn load(
+        data_dir: Option<&str>,
+        encoding: Option<Charset>,
+        progress: &mut dyn FnMut(LoadProgress),
+    ) -> Result<Dictionaries, DictError> {
+        let dict_dir = locate_data_dir(data_dir)?;
+        info!("Loading dictionaries from {:?}", dict_dir);
+        let mut load_progress = LoadProgress {
+            files_loaded: 0,
+            files_total: 2,
+            entries_parsed: 0,
+        };
+        progress(load_progress);
+        let en_bg = {
+            let mut file_progress = |entries_parsed: usize| {
+                progress(LoadProgress {
+                    entries_parsed: load_progress.entries_parsed + entries_parsed,
+                    ..load_progress
+                })
+            };
+            load_dictionary(Direction::EnBg, &dict_file(&dict_dir, Direction::EnBg), encoding, &mut file_progress)?
+        };
+        load_progress.files_loaded += 1;
+        load_progress.entries_parsed += en_bg.len();
+        progress(load_progress);
+        let bg_en = {
+            let mut file_progress = |entries_parsed: usize| {
+                progress(LoadProgress {
+                    entries_parsed: load_progress.entries_parsed + entries_parsed,
+                    ..load_progress
+                })
+            };
+            load_dictionary(Direction::BgEn, &dict_file(&dict_dir, Direction::BgEn), encoding, &mut file_progress)?
+        };
+        load_progress.files_loaded += 1;
+        load_progress.entries_parsed += bg_en.len();
+        progress(load_progress);
         info!(
             "This database contains {} elements",
-            concatenated_dictionaries.len()
+            en_bg.len() + bg_en.len()
         );
-        //concatenated_dictionaries.sort();
         info!("Done");
-        concatenated_dictionaries
+        Ok(Dictionaries {
+            en_bg,
+            bg_en,
+            extra: Vec::new(),
+        })
+    }
+
+    /// Load an extra dictionary from `source`.
+    /// Returns the key of the dictionary, which is searched for like the bedic ones.
+    pub fn add_source(
+        &mut self,
+        source: &dyn DictionarySource,
+        progress: &mut dyn FnMut(usize),
+    ) -> Result<DictKey, DictError> {
+        let dictionary = Dictionary::from_source(source, progress)?;
+        info!("Loaded {:?} with {} entries", dictionary.name, dictionary.len());
+        self.extra.push(dictionary);
+        Ok(DictKey::Extra(self.extra.len() - 1))
+    }
+
+    /// Build the search indices of all dictionaries, see `Dictionary::build_search_indexes()`
+    pub fn build_search_indexes(&self) {
+        for dictionary in [&self.en_bg, &self.bg_en].iter().cloned().chain(self.extra.iter()) {
+            dictionary.build_search_indexes();
+        }
+    }
+
+    pub fn get(&self, direction: Direction) -> &Dictionary {
+        match direction {
+            Direction::EnBg => &self.en_bg,
+            Direction::BgEn => &self.bg_en,
+        }
+    }
+
+    /// The dictionary identified by `key`, `None` for unknown extra dictionaries
+    pub fn by_key(&self, key: DictKey) -> Option<&Dictionary> {
+        match key {
+            DictKey::Bedic(direction) => Some(self.get(direction)),
+            DictKey::Extra(index) => self.extra.get(index),
+        }
     }
-    /// Load database from history file
-    pub fn new_history(history_file_path: &str) -> Vec<DictDB> {
-        // read from history files
-        debug!("History file: {}", history_file_path);
-        let mut string_history = String::new();
-        // TODO: Add Windows support
-        {
-            info!("Loading history from file");
-            match File::open(history_file_path) {
-                Ok(mut file_history) => {
-                    file_history
-                        .read_to_string(&mut string_history)
-                        .expect("Unable to read history file");
-                },
-                Err(e) => {
-                    error!("Can not open history file: {:?}", e);
-                    return Vec::new();
-                }
-            };
 
+    /// Search for words starting with `searched_txt` into the dictionary of `direction`.
+    /// When `direction` is `None`, it is detected by the script of `searched_txt`.
+    pub fn search(
+        &self,
+        searched_txt: &str,
+        direction: Option<Direction>,
+    ) -> Result<Vec<DictDB>, Vec<DictDB>> {
+        let direction = direction.unwrap_or_else(|| Direction::detect(searched_txt));
+        self.get(direction).search(searched_txt)
+    }
+
+    /// Search for up to `limit` words similar to `searched_txt`, see `Dictionary::fuzzy_search()`.
+    /// When `direction` is `None`, it is detected by the script of `searched_txt`.
+    pub fn fuzzy_search(
+        &self,
+        searched_txt: &str,
+        direction: Option<Direction>,
+        limit: usize,
+    ) -> Vec<FuzzyMatch> {
+        let direction = direction.unwrap_or_else(|| Direction::detect(searched_txt));
+        self.get(direction).fuzzy_search(searched_txt, limit)
+    }
+
+    /// Search for up to `limit` entries whose translation contains `searched_txt`,
+    /// see `Dictionary::fulltext_search()`. When `direction` is `None`, the dictionary
+    /// translating to the script of `searched_txt` is used,
+    /// e.g. Bulgarian text is searched for into the EN→BG translations.
+    pub fn fulltext_search(
+        &self,
+        searched_txt: &str,
+        direction: Option<Direction>,
+        limit: usize,
+    ) -> Vec<FullTextMatch> {
+        let direction = direction.unwrap_or_else(|| Direction::detect(searched_txt).opposite());
+        self.get(direction).fulltext_search(searched_txt, limit)
+    }
+}
+
+/// Directory containing both dictionary files, see `find_data_dir()`
+fn locate_data_dir(data_dir: Option<&str>) -> Result<PathBuf, DictError> {
+    // TODO: Add Windows support
+    find_data_dir(data_dir).ok_or_else(|| {
+        let tried: Vec<PathBuf> = data_dirs(data_dir)
+            .iter()
+            .flat_map(|dir| {
+                dict_file_names(Direction::EnBg)
+                    .iter()
+                    .chain(dict_file_names(Direction::BgEn).iter())
+                    .map(|file_name| dir.join(file_name))
+                    .collect::<Vec<PathBuf>>()
+            })
+            .collect();
+        DictError::NotFound(tried)
+    })
+}
+
+/// Load one dictionary file from its compiled index, if it is up to date.
+/// Otherwise read and parse the file, then compile its index for the next start.
+/// `progress` gets the number of entries parsed so far.
+fn load_dictionary(
+    direction: Direction,
+    path: &Path,
+    encoding: Option<Charset>,
+    progress: &mut dyn FnMut(usize),
+) -> Result<Dictionary, DictError> {
+    let index_path = index::cache_path(path);
+    if let Some(ref index_path) = index_path {
+        match CompiledIndex::open(index_path, path, direction, encoding) {
+            Ok(compiled_index) => {
+                info!("Map {:?} from {:?}", path, index_path);
+                progress(compiled_index.len());
+                return Ok(Dictionary::from_index(compiled_index));
+            }
+            Err(err) => debug!("Index {:?} not used: {}", index_path, err),
+        }
+    }
+    let (entries, file_content) = parse_dictionary(direction, path, encoding, progress)?;
+    if let Some(ref index_path) = index_path {
+        if let Err(err) = index::write(index_path, direction, path, &file_content.data, file_content.charset, &entries) {
+            warn!("Failed to write index {:?}: {}", index_path, err);
         }
-        info!("Parse the history file");
-        let vector_history = parse_history(&string_history);
+    }
+    Ok(Dictionary::from_entries(direction, entries))
+}
+
+/// Read and parse one dictionary file, returns the sorted entries and the content
+fn parse_dictionary(
+    direction: Direction,
+    path: &Path,
+    encoding: Option<Charset>,
+    progress: &mut dyn FnMut(usize),
+) -> Result<(Vec<DictDB>, DictFile), DictError> {
+    let file_content = read_dict_file(path, encoding)?;
+    info!("Parse {:?} ({})", path, file_content.charset);
+    let mut entries = parse_with_progress(&file_content.text, direction, progress);
+    if entries.is_empty() {
+        return Err(DictError::Parse(path.to_path_buf()));
+    }
+    entries.sort();
+    Ok((entries, file_content))
+}
+
+/// Compile the indices of both dictionary files into `index::cache_dir()`,
+/// replacing the existing ones. Returns the paths of the indices.
+/// When `encoding` is `None`, the encoding of the files is detected.
+pub fn compile_indexes(
+    data_dir: Option<&str>,
+    encoding: Option<Charset>,
+) -> Result<Vec<PathBuf>, DictError> {
+    let dict_dir = locate_data_dir(data_dir)?;
+    let mut index_paths: Vec<PathBuf> = Vec::new();
+    for direction in &[Direction::EnBg, Direction::BgEn] {
+        let path = dict_file(&dict_dir, *direction);
+        let index_path = index_path_of(&path)?;
+        let (entries, file_content) = parse_dictionary(*direction, &path, encoding, &mut |_| ())?;
+        index::write(&index_path, *direction, &path, &file_content.data, file_content.charset, &entries)
+            .map_err(|err| DictError::Io(index_path.clone(), err))?;
+        index_paths.push(index_path);
+    }
+    Ok(index_paths)
+}
+
+/// Check the compiled indices of both dictionary files, see `CompiledIndex::verify()`.
+/// Returns the paths of the indices.
+pub fn check_indexes(
+    data_dir: Option<&str>,
+    encoding: Option<Charset>,
+) -> Result<Vec<PathBuf>, DictError> {
+    let dict_dir = locate_data_dir(data_dir)?;
+    let mut index_paths: Vec<PathBuf> = Vec::new();
+    for direction in &[Direction::EnBg, Direction::BgEn] {
+        let path = dict_file(&dict_dir, *direction);
+        let index_path = index_path_of(&path)?;
+        CompiledIndex::open(&index_path, &path, *direction, encoding)
+            .and_then(|compiled_index| compiled_index.verify(&path))
+            .map_err(|err| DictError::Io(index_path.clone(), err))?;
+        index_paths.push(index_path);
+    }
+    Ok(index_paths)
+}
+
+/// Path of the compiled index of the dictionary file `path`, see `index::cache_path()`
+fn index_path_of(path: &Path) -> Result<PathBuf, DictError> {
+    index::cache_path(path).ok_or_else(|| {
+        DictError::Io(
+            path.to_path_buf(),
+            io::Error::new(ErrorKind::NotFound, "no cache directory"),
+        )
+    })
+}
+
+/// Read and parse the dictionary of `direction` without its compiled index,
+/// for lookups when `open_index()` fails, e.g. the index can't be written.
+/// When `encoding` is `None`, the encoding of the file is detected.
+pub fn read_dictionary(
+    data_dir: Option<&str>,
+    direction: Direction,
+    encoding: Option<Charset>,
+) -> Result<Dictionary, DictError> {
+    let dict_dir = locate_data_dir(data_dir)?;
+    let (entries, _) = parse_dictionary(direction, &dict_file(&dict_dir, direction), encoding, &mut |_| ())?;
+    Ok(Dictionary::from_entries(direction, entries))
+}
+
+/// Open the compiled index of the dictionary of `direction` for lookups without
+/// loading the whole dictionary. The index is compiled first, if it is missing or stale.
+/// When `encoding` is `None`, the encoding of the file is detected.
+pub fn open_index(
+    data_dir: Option<&str>,
+    direction: Direction,
+    encoding: Option<Charset>,
+) -> Result<CompiledIndex, DictError> {
+    let dict_dir = locate_data_dir(data_dir)?;
+    let path = dict_file(&dict_dir, direction);
+    let index_path = index_path_of(&path)?;
+    if let Ok(compiled_index) = CompiledIndex::open(&index_path, &path, direction, encoding) {
+        return Ok(compiled_index);
+    }
+    let (entries, file_content) = parse_dictionary(direction, &path, encoding, &mut |_| ())?;
+    index::write(&index_path, direction, &path, &file_content.data, file_content.charset, &entries)
+        .and_then(|_| CompiledIndex::open(&index_path, &path, direction, encoding))
+        .map_err(|err| DictError::Io(index_path.clone(), err))
+}
+
+impl DictDB {
+    /// Load database from history file, see `HistoryFile::load()`.
+    /// A missing or unreadable history file results in an empty database.
+    pub fn new_history(history_file_path: &str) -> Vec<DictDB> {
+        debug!("History file: {}", history_file_path);
+        info!("Loading history from file");
+        let vector_history: Vec<DictDB> = match HistoryFile::new(history_file_path).load() {
+            Ok(records) => records.iter().map(HistoryRecord::to_dict_db).collect(),
+            Err(e) => {
+                error!("Can not read history file: {:?}", e);
+                return Vec::new();
+            }
+        };
         info!(
             "This history file contains {} elements",
             vector_history.len()
@@ -88,6 +776,10 @@ impl DictDB {
         vector_history
     }
 
+    /// Search sorted `data` for words starting with `searched_txt` (case insensitive).
+    ///
+    /// Returns `Ok` when the exact word is found and `Err` otherwise.
+    /// Both variants hold all entries starting with `searched_txt`, in alphabetical order.
     #[inline(always)]
     pub fn search(searched_txt: &str, data: &Vec<DictDB>) -> Result<Vec<DictDB>, Vec<DictDB>> {
         //let mut vec_dict_db: Vec<DictDB> = Vec::new();
@@ -95,14 +787,115 @@ impl DictDB {
         let search_struct = DictDB {
             word: searched_txt.to_string().to_uppercase(),
             translation: "_".to_string(),
+            direction: Direction::detect(searched_txt),
         };
         match data.my_binary_search(&search_struct) {
-            Ok(vec_result) => return Ok(vec_result),
-            Err(vec_result_err) => return Err(vec_result_err),
-        };
+            Ok(vec_result) => Ok(vec_result),
+            Err(vec_result_err) => Err(vec_result_err),
+        }
     }
 }
 
+/// Errors which can occur while loading the dictionary files
+#[derive(Debug)]
+pub enum DictError {
+    /// None of the searched paths contains the dictionary files
+    NotFound(Vec<PathBuf>),
+    /// The file exists, but the current user is not allowed to read it
+    Permission(PathBuf),
+    /// The file is not valid UTF-8, while UTF-8 is configured
+    Encoding(PathBuf),
+    /// The file does not contain any dictionary entries
+    Parse(PathBuf),
+    /// The file is not in the expected format, e.g. a broken StarDict index
+    Format(PathBuf, String),
+    /// Any other I/O error
+    Io(PathBuf, io::Error),
+}
+
+impl DictError {
+    /// Short instructions how to install the dictionary files
+    pub fn install_hint(&self) -> &'static str {
+        "Get en_bg-utf8.dat and bg_en-utf8.dat from
+https://github.com/idzhonev/bedic_data-utf8/releases
+and copy them to ~/.local/share/bedic/ or /usr/local/share/bedic/,
+or point rbedic to them with --data-dir <DIR> or RBEDIC_DATA_DIR.
+The original kbedic files en_bg.dat and bg_en.dat can be used as well."
+    }
+}
+
+impl fmt::Display for DictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DictError::NotFound(ref tried) => {
+                write!(f, "Unable to find the dictionary files. Tried:")?;
+                for path in tried {
+                    write!(f, "\n  {}", path.display())?;
+                }
+                Ok(())
+            }
+            DictError::Permission(ref path) => {
+                write!(f, "Permission denied to read {}", path.display())
+            }
+            DictError::Encoding(ref path) => write!(
+                f,
+                "The file {} is not valid UTF-8, try --encoding cp1251",
+                path.display()
+            ),
+            DictError::Parse(ref path) => {
+                write!(f, "The file {} does not contain dictionary entries", path.display())
+            }
+            DictError::Format(ref path, ref message) => {
+                write!(f, "Invalid dictionary file {}: {}", path.display(), message)
+            }
+            DictError::Io(ref path, ref err) => {
+                write!(f, "Unable to read {}: {}", path.display(), err)
+            }
+        }
+    }
+}
+
+impl Error for DictError {}
+
+/// Map an I/O error reading `path` to `DictError`
+pub(crate) fn io_error(path: &Path, err: io::Error) -> DictError {
+    match err.kind() {
+        ErrorKind::NotFound => DictError::NotFound(vec![path.to_path_buf()]),
+        ErrorKind::PermissionDenied => DictError::Permission(path.to_path_buf()),
+        _ => DictError::Io(path.to_path_buf(), err),
+    }
+}
+
+/// Content of a dictionary file
+struct DictFile {
+    /// Bytes as read from the file
+    data: Vec<u8>,
+    /// Encoding of `data`
+    charset: Charset,
+    /// `data` decoded from `charset`
+    text: String,
+}
+
+/// Read whole dictionary file and decode it from `encoding`, or from the detected
+/// encoding if it is `None`. I/O errors are mapped to `DictError`.
+fn read_dict_file(path: &Path, encoding: Option<Charset>) -> Result<DictFile, DictError> {
+    let mut data: Vec<u8> = Vec::new();
+    File::open(path)
+        .and_then(|mut file| file.read_to_end(&mut data))
+        .map_err(|err| io_error(path, err))?;
+    let charset = encoding.unwrap_or_else(|| Charset::detect(&data));
+    let text = match charset {
+        Charset::Utf8 => String::from_utf8(data.clone())
+            .map_err(|_| DictError::Encoding(path.to_path_buf()))?,
+        _ => charset::decode(&data, charset),
+    };
+    Ok(DictFile {
+        data,
+        charset,
+        text,
+    })
+}
+
 impl Ord for DictDB {
     #[inline(always)]
     fn cmp(&self, other: &DictDB) -> Ordering {
@@ -130,107 +923,82 @@ trait VecDictDB {
     where
         Self::Item: Ord;
 
-    fn my_binary_search_by<'a, F>(&'a self, f: F) -> Result<usize, usize>
+    fn my_prefix_range(&self, prefix: &str) -> Range<usize>;
+
+    fn my_partition_point<F>(&self, pred: F) -> usize
     where
-        F: FnMut(&'a DictDB) -> Ordering;
+        F: FnMut(&DictDB) -> bool;
 }
 
 impl VecDictDB for Vec<DictDB> {
     type Item = Vec<DictDB>;
 
+    /// Return all entries starting with `x.word`: `Ok` if `x.word` itself is found,
+    /// `Err` otherwise.
     #[inline(always)]
     fn my_binary_search(&self, x: &DictDB) -> Result<Self::Item, Self::Item>
     where
         DictDB: Ord,
     {
-        let vec_data = self;
-        let size = vec_data.len();
-        // Search for prefix x
-        match self.my_binary_search_by(|p| p.cmp(x)) {
-            Ok(index) => {
-                let mut result_vector: Vec<DictDB> = Vec::new();
-                let mut matched_suffix: bool = false;
-
-                let mut j: usize = index; // Start
-                let mut number = j + PREFIX_NUMBER; // End
-                if number >= size {
-                    number = size;
-                };
-                for i in j..number {
-                    let s = vec_data[i].word.clone();
-                    if s.as_str().starts_with(&x.word) {
-                        matched_suffix = true;
-                        result_vector.push(vec_data[i].clone());
-                    } else {
-                        if matched_suffix {
-                            break;
-                        }
-                    }
-
-                    j += 1;
-                }
-                Ok(result_vector)
-            }
-            Err(index_err) => {
-                let mut result_vector: Vec<DictDB> = Vec::new();
-                let mut matched_suffix: bool = false;
-
-                let mut j: usize = index_err; // Start
-                let mut number = j + PREFIX_NUMBER; // End
-                if number >= size {
-                    number = size;
-                };
-                for i in j..number {
-                    let s = vec_data[i].word.clone();
-                    if s.as_str().starts_with(&x.word) {
-                        matched_suffix = true;
-                        result_vector.push(vec_data[i].clone());
-                    } else {
-                        if matched_suffix {
-                            break;
-                        }
-                    }
-
-                    j += 1;
-                }
-                Err(result_vector)
-            }
+        let range = self.my_prefix_range(&x.word);
+        let exact_match = range.start < range.end && self[range.start].word == x.word;
+        let result_vector: Vec<DictDB> = self[range].to_vec();
+        if exact_match {
+            Ok(result_vector)
+        } else {
+            Err(result_vector)
         }
     }
 
+    /// Range of the sorted entries starting with `prefix`:
+    /// from the lower bound (first word not less than `prefix`)
+    /// to the upper bound (first word after it not starting with `prefix`).
     #[inline(always)]
-    fn my_binary_search_by<'a, F>(&'a self, mut f: F) -> Result<usize, usize>
+    fn my_prefix_range(&self, prefix: &str) -> Range<usize> {
+        let lower_bound = self.my_partition_point(|p| p.word.as_str() < prefix);
+        let upper_bound =
+            self.my_partition_point(|p| p.word.as_str() < prefix || p.word.starts_with(prefix));
+        lower_bound..upper_bound
+    }
+
+    /// Index of the first entry for which `pred` is false,
+    /// `pred` has to be true for all entries before it and false after it.
+    #[inline(always)]
+    fn my_partition_point<F>(&self, mut pred: F) -> usize
     where
-        F: FnMut(&'a DictDB) -> Ordering,
+        F: FnMut(&DictDB) -> bool,
     {
-        let s = self;
-        let mut size = s.len();
-        if size == 0 {
-            return Err(0);
-        }
         let mut base = 0usize;
-        while size > 1 {
+        let mut size = self.len();
+        while size > 0 {
             let half = size / 2;
             let mid = base + half;
-            // mid is always in [0, size).
-            // mid >= 0: by definition
-            // mid < size: mid = size / 2 + size / 4 + size / 8 ...
-            let cmp = f(unsafe { s.get_unchecked(mid) });
-            base = if cmp == Greater { base } else { mid };
-            size -= half;
-        }
-        // base is always in [0, size) because base <= mid.
-        let cmp = f(unsafe { s.get_unchecked(base) });
-        if cmp == Equal {
-            Ok(base)
-        } else {
-            Err(base + (cmp == Less) as usize)
+            if pred(&self[mid]) {
+                base = mid + 1;
+                size -= half + 1;
+            } else {
+                size = half;
+            }
         }
+        base
     }
 }
 
-/// Parse db files
-fn parse(string_data: &str) -> Vec<DictDB> {
+/// Parse the content of a bedic `.dat` file.
+///
+/// Entries are delimited by `^;`. The first line of an entry is the word,
+/// the whole entry (including the first line) is kept as translation.
+/// The result is not sorted.
+pub fn parse(string_data: &str, direction: Direction) -> Vec<DictDB> {
+    parse_with_progress(string_data, direction, &mut |_| ())
+}
+
+/// Parse like `parse()`, reporting the number of entries parsed so far to `progress`
+pub fn parse_with_progress(
+    string_data: &str,
+    direction: Direction,
+    progress: &mut dyn FnMut(usize),
+) -> Vec<DictDB> {
     let mut vec_dict_db: Vec<DictDB> = Vec::new();
     let data: Vec<String> = string_data.split("^;").map(|s| s.to_string()).collect();
     for i in &data {
@@ -238,30 +1006,42 @@ fn parse(string_data: &str) -> Vec<DictDB> {
             static ref ITEM: Regex = Regex::new(r"^(.*)\n(.|\s)*").unwrap();
         }
         if ITEM.is_match(i) {
-            for cap in ITEM.captures(i) {
+            if let Some(cap) = ITEM.captures(i) {
                 let dict_db = DictDB {
                     word: cap[1].to_string(),
                     translation: cap[0].to_string(),
+                    direction,
                 };
                 vec_dict_db.push(dict_db);
+                if vec_dict_db.len().is_multiple_of(PROGRESS_STEP) {
+                    progress(vec_dict_db.len());
+                }
             }
         }
     }
+    progress(vec_dict_db.len());
     vec_dict_db
 }
-/// Parse db files
-fn parse_history(string_data: &str) -> Vec<DictDB> {
+/// Parse the content of a history file of the old format, see `history`.
+///
+/// Entries are delimited by a line of 78 dashes, as written by rbedic 0.2 and older,
+/// see `history::ENTRY_DELIMITER`.
+/// The first line of an entry is the word. The result keeps the order of the file.
+/// The direction of each entry is detected by the script of its word.
+pub fn parse_history(string_data: &str) -> Vec<DictDB> {
     let mut vec_dict_db: Vec<DictDB> = Vec::new();
-    let data: Vec<String> = string_data.split("------------------------------------------------------------------------------\n").map(|s| s.to_string()).collect();
+    let delimiter = format!("{}\n", ENTRY_DELIMITER);
+    let data: Vec<String> = string_data.split(delimiter.as_str()).map(|s| s.to_string()).collect();
     for i in &data {
         lazy_static! {
             static ref ITEM: Regex = Regex::new(r"^(.*)\n(.|\s)*").unwrap();
         }
         if ITEM.is_match(i) {
-            for cap in ITEM.captures(i) {
+            if let Some(cap) = ITEM.captures(i) {
                 let dict_db = DictDB {
                     word: cap[1].to_string(),
                     translation: cap[0].to_string(),
+                    direction: Direction::detect(&cap[1]),
                 };
                 vec_dict_db.push(dict_db);
             }
@@ -269,3 +1049,45 @@ fn parse_history(string_data: &str) -> Vec<DictDB> {
     }
     vec_dict_db
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(words: &[&str]) -> Vec<DictDB> {
+        words
+            .iter()
+            .map(|word| DictDB {
+                word: word.to_string(),
+                translation: format!("{}\nn", word),
+                direction: Direction::EnBg,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prefix_ranges() {
+        let entries = entries(&["BOOK", "CO", "COAT", "COCOA", "CUP", "ZOO"]);
+        assert_eq!(entries.my_prefix_range("CO"), 1..4);
+        assert_eq!(entries.my_prefix_range("COC"), 3..4);
+        // An empty prefix matches all entries
+        assert_eq!(entries.my_prefix_range(""), 0..6);
+        // No match: an empty range at the position the prefix would take
+        assert_eq!(entries.my_prefix_range("CA"), 1..1);
+        assert_eq!(entries.my_prefix_range("A"), 0..0);
+        assert_eq!(entries.my_prefix_range("ZZ"), 6..6);
+        // The last entry
+        assert_eq!(entries.my_prefix_range("ZO"), 5..6);
+        assert_eq!(entries.my_prefix_range("ZOO"), 5..6);
+        assert_eq!(Vec::new().my_prefix_range("CO"), 0..0);
+    }
+
+    #[test]
+    fn prefix_ranges_ignore_case() {
+        let dictionary = Dictionary::new(Direction::EnBg, "COAT\nn палто\n^;CO\nabbr компания\n^;CUP\nn чаша\n^;");
+        assert_eq!(dictionary.prefix_range("co"), 0..2);
+        assert_eq!(dictionary.prefix_range("Co"), 0..2);
+        assert_eq!(dictionary.prefix_range("CO"), 0..2);
+        assert_eq!(dictionary.prefix_range("coa"), 1..2);
+    }
+}
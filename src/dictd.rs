@@ -0,0 +1,478 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Server of the DICT protocol (RFC 2229), for clients like `dict`, GoldenDict
+//! or Emacs dictionary.el. Each bedic dictionary is a database, named by its
+//! direction: `en_bg` and `bg_en`.
+
+use std::collections::HashSet;
+use std::io::{self, BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::process;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use regex::Regex;
+
+use database::{DictDB, Dictionaries, Dictionary, Direction};
+
+/// Strategies of MATCH, with their descriptions
+const STRATEGIES: &[(&str, &str)] = &[
+    ("exact", "Match headwords exactly"),
+    ("prefix", "Match prefixes"),
+    ("re", "Case insensitive regular expression"),
+    ("lev", "Match headwords with a few typos (Levenshtein distance)"),
+];
+
+/// Strategy of MATCH with the `.` strategy
+const DEFAULT_STRATEGY: &str = "prefix";
+
+/// Number of clients served at once, the next ones are told to come back later
+pub const MAX_CONNECTIONS: usize = 32;
+
+/// Longest command line accepted, including CRLF, see RFC 2229 section 2.2
+pub const MAX_LINE_LEN: usize = 1024;
+
+/// Clients which send no command within this time are disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// MIME headers sent before each text when the client asked for OPTION MIME
+const MIME_HEADERS: &str = "Content-type: text/plain; charset=utf-8\r\nContent-transfer-encoding: 8bit\r\n";
+
+/// A word matching strategy of MATCH
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Strategy {
+    Exact,
+    Prefix,
+    Regex,
+    Levenshtein,
+}
+
+impl Strategy {
+    /// The strategy named `name` by a client, `.` is the default one.
+    /// The long names `regex` and `levenshtein` are accepted as well.
+    fn from_name(name: &str) -> Option<Strategy> {
+        let name = if name == "." { DEFAULT_STRATEGY } else { name };
+        match name.to_lowercase().as_str() {
+            "exact" => Some(Strategy::Exact),
+            "prefix" => Some(Strategy::Prefix),
+            "re" | "regex" => Some(Strategy::Regex),
+            "lev" | "levenshtein" => Some(Strategy::Levenshtein),
+            _ => None,
+        }
+    }
+}
+
+/// DICT server of the bedic dictionaries
+pub struct DictServer {
+    listener: TcpListener,
+    dictionaries: Arc<Dictionaries>,
+    /// Number of words returned by MATCH
+    limit: usize,
+}
+
+impl DictServer {
+    /// Listen on `addr`, e.g. `127.0.0.1:2628`.
+    /// `limit` is the number of words returned by MATCH.
+    pub fn bind<A: ToSocketAddrs>(addr: A, dictionaries: Arc<Dictionaries>, limit: usize) -> io::Result<DictServer> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(DictServer {
+            listener,
+            dictionaries,
+            limit,
+        })
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve the clients, each one on its own thread, up to `MAX_CONNECTIONS` at once.
+    /// Returns only on errors of the listener.
+    pub fn run(&self) -> io::Result<()> {
+        info!("DICT server listening on {}", self.local_addr()?);
+        let connections = AtomicUsize::new(0);
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in self.listener.incoming() {
+            let mut stream = stream?;
+            let connection = connections.fetch_add(1, Ordering::SeqCst);
+            if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::SeqCst);
+                warn!("DICT connection {}: {} clients already, refused", connection, MAX_CONNECTIONS);
+                let _ = write!(stream, "420 server temporarily unavailable\r\n");
+                continue;
+            }
+            let dictionaries = self.dictionaries.clone();
+            let limit = self.limit;
+            let active = active.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                debug!("DICT connection {} from {:?}", connection, peer);
+                if let Err(err) = serve_client(stream, &dictionaries, limit, connection) {
+                    warn!("DICT connection {} from {:?}: {}", connection, peer, err);
+                }
+                active.fetch_sub(1, Ordering::SeqCst);
+                debug!("DICT connection {} closed", connection);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Speak the protocol with one client, until it sends QUIT or closes the connection.
+/// Lines longer than `MAX_LINE_LEN` are rejected, clients which stay silent
+/// for `READ_TIMEOUT` are disconnected.
+fn serve_client(stream: TcpStream, dictionaries: &Dictionaries, limit: usize, connection: usize) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut session = Session {
+        dictionaries,
+        limit,
+        writer: stream,
+        mime: false,
+    };
+    // The capabilities are empty: neither AUTH nor the extensions are supported
+    session.status(
+        220,
+        &format!("rbedic DICT server <> <{}.{}@rbedic>", process::id(), connection),
+    )?;
+    let mut line: Vec<u8> = Vec::new();
+    loop {
+        line.clear();
+        match reader.by_ref().take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line) {
+            Ok(0) => break,
+            Ok(_) => (),
+            Err(ref err) if err.kind() == ErrorKind::WouldBlock || err.kind() == ErrorKind::TimedOut => {
+                debug!("DICT connection {}: timed out", connection);
+                break;
+            }
+            Err(err) => return Err(err),
+        }
+        if line.len() == MAX_LINE_LEN && !line.ends_with(b"\n") {
+            // The rest of the line is skipped without keeping it
+            while !line.is_empty() && !line.ends_with(b"\n") {
+                line.clear();
+                reader.by_ref().take(MAX_LINE_LEN as u64).read_until(b'\n', &mut line)?;
+            }
+            session.status(500, "line too long")?;
+            continue;
+        }
+        let line = String::from_utf8_lossy(&line);
+        trace!("DICT connection {}: {:?}", connection, line);
+        if !session.command(&line)? {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// State of the connection with one client
+struct Session<'a, W: Write> {
+    dictionaries: &'a Dictionaries,
+    /// Number of words returned by MATCH
+    limit: usize,
+    writer: W,
+    /// OPTION MIME was sent: texts are preceded by MIME headers
+    mime: bool,
+}
+
+impl<'a, W: Write> Session<'a, W> {
+    /// Execute one command line. Returns false after QUIT.
+    fn command(&mut self, line: &str) -> io::Result<bool> {
+        let words = match split_command(line) {
+            Some(words) => words,
+            None => return self.status(501, "syntax error, illegal parameters").map(|_| true),
+        };
+        let words: Vec<&str> = words.iter().map(|word| word.as_str()).collect();
+        let command: Vec<String> = words.iter().take(2).map(|word| word.to_uppercase()).collect();
+        let command: Vec<&str> = command.iter().map(|word| word.as_str()).collect();
+        match (command.as_slice(), words.len()) {
+            ([], _) => return Ok(true),
+            (["DEFINE", ..], 3) => self.define(words[1], words[2])?,
+            (["MATCH", ..], 4) => self.match_words(words[1], words[2], words[3])?,
+            (["SHOW", "DB"], 2) | (["SHOW", "DATABASES"], 2) => self.show_databases()?,
+            (["SHOW", "STRAT"], 2) | (["SHOW", "STRATEGIES"], 2) => self.show_strategies()?,
+            (["SHOW", "INFO"], 3) => self.show_info(words[2])?,
+            (["SHOW", "SERVER"], 2) => self.show_server()?,
+            (["OPTION", "MIME"], 2) => {
+                self.mime = true;
+                self.status(250, "ok - using MIME headers")?;
+            }
+            (["CLIENT", ..], _) => self.status(250, "ok")?,
+            (["STATUS"], 1) => self.status(210, "status ok")?,
+            (["HELP"], 1) => self.help()?,
+            (["QUIT"], 1) => {
+                self.status(221, "bye")?;
+                return Ok(false);
+            }
+            (["AUTH", ..], _) | (["SASLAUTH", ..], _) | (["OPTION", ..], _) => {
+                self.status(502, "command not implemented")?
+            }
+            (["DEFINE", ..], _) | (["MATCH", ..], _) | (["SHOW", ..], _) | (["STATUS"], _)
+            | (["HELP"], _) | (["QUIT"], _) => self.status(501, "syntax error, illegal parameters")?,
+            _ => self.status(500, "unknown command")?,
+        }
+        Ok(true)
+    }
+
+    /// DEFINE: the entries of `word` (case insensitive) from `database`
+    fn define(&mut self, database: &str, word: &str) -> io::Result<()> {
+        let databases = match self.select(database) {
+            Some(databases) => databases,
+            None => return self.status(550, "invalid database, use \"SHOW DB\" for list of databases"),
+        };
+        let mut definitions: Vec<(Direction, DictDB)> = Vec::new();
+        for direction in databases {
+            definitions.extend(
+                exact_entries(self.dictionaries.get(direction), word)
+                    .into_iter()
+                    .map(|entry| (direction, entry)),
+            );
+            if database == "!" && !definitions.is_empty() {
+                break;
+            }
+        }
+        if definitions.is_empty() {
+            return self.status(552, "no match");
+        }
+        self.status(150, &format!("{} definitions retrieved", definitions.len()))?;
+        for (direction, entry) in definitions {
+            self.status(
+                151,
+                &format!("{} {} {}", quote(&entry.word), direction.id(), quote(description(direction))),
+            )?;
+            self.text(&entry.translation)?;
+        }
+        self.status(250, "ok")
+    }
+
+    /// MATCH: the words of `database` matching `word` with `strategy`, up to `limit`
+    fn match_words(&mut self, database: &str, strategy: &str, word: &str) -> io::Result<()> {
+        let strategy = match Strategy::from_name(strategy) {
+            Some(strategy) => strategy,
+            None => return self.status(551, "invalid strategy, use \"SHOW STRAT\" for a list of strategies"),
+        };
+        let databases = match self.select(database) {
+            Some(databases) => databases,
+            None => return self.status(550, "invalid database, use \"SHOW DB\" for list of databases"),
+        };
+        let regex = if strategy == Strategy::Regex {
+            match Regex::new(&format!("(?i){}", word)) {
+                Ok(regex) => Some(regex),
+                Err(err) => {
+                    debug!("DICT MATCH: invalid regular expression {:?}: {}", word, err);
+                    return self.status(552, "no match");
+                }
+            }
+        } else {
+            None
+        };
+        let mut matches: Vec<(Direction, String)> = Vec::new();
+        for direction in databases {
+            let dictionary = self.dictionaries.get(direction);
+            let remaining = self.limit - matches.len();
+            let mut words: Vec<String> = match (strategy, regex.as_ref()) {
+                (Strategy::Exact, _) => exact_entries(dictionary, word).into_iter().map(|entry| entry.word).collect(),
+                (Strategy::Prefix, _) => dictionary
+                    .prefix_range(word)
+                    .take(remaining)
+                    .map(|index| dictionary.word(index).to_string())
+                    .collect(),
+                (Strategy::Regex, Some(regex)) => dictionary
+                    .pattern_search(regex)
+                    .take(remaining)
+                    .map(|entry| entry.word)
+                    .collect(),
+                (Strategy::Levenshtein, _) => dictionary
+                    .fuzzy_search(word, remaining)
+                    .into_iter()
+                    .map(|fuzzy_match| fuzzy_match.entry.word)
+                    .collect(),
+                (Strategy::Regex, None) => Vec::new(),
+            };
+            // Homonyms have an entry each, the fuzzy matches are not sorted
+            let mut seen: HashSet<String> = HashSet::new();
+            words.retain(|word| seen.insert(word.clone()));
+            words.truncate(remaining);
+            matches.extend(words.into_iter().map(|word| (direction, word)));
+            if matches.len() == self.limit || (database == "!" && !matches.is_empty()) {
+                break;
+            }
+        }
+        if matches.is_empty() {
+            return self.status(552, "no match");
+        }
+        self.status(152, &format!("{} matches found", matches.len()))?;
+        let lines: Vec<String> = matches
+            .iter()
+            .map(|&(direction, ref word)| format!("{} {}", direction.id(), quote(word)))
+            .collect();
+        self.lines(&lines)?;
+        self.status(250, "ok")
+    }
+
+    /// SHOW DB: names and descriptions of the databases
+    fn show_databases(&mut self) -> io::Result<()> {
+        let directions = [Direction::EnBg, Direction::BgEn];
+        self.status(110, &format!("{} databases present", directions.len()))?;
+        let lines: Vec<String> = directions
+            .iter()
+            .map(|direction| format!("{} {}", direction.id(), quote(description(*direction))))
+            .collect();
+        self.lines(&lines)?;
+        self.status(250, "ok")
+    }
+
+    /// SHOW STRAT: names and descriptions of the strategies
+    fn show_strategies(&mut self) -> io::Result<()> {
+        self.status(111, &format!("{} strategies present", STRATEGIES.len()))?;
+        let lines: Vec<String> = STRATEGIES
+            .iter()
+            .map(|&(name, strategy_description)| format!("{} {}", name, quote(strategy_description)))
+            .collect();
+        self.lines(&lines)?;
+        self.status(250, "ok")
+    }
+
+    /// SHOW INFO: description of one database
+    fn show_info(&mut self, database: &str) -> io::Result<()> {
+        let direction = match database.parse::<Direction>() {
+            Ok(direction) => direction,
+            Err(_) => return self.status(550, "invalid database, use \"SHOW DB\" for list of databases"),
+        };
+        self.status(112, &format!("database information follows for {}", direction.id()))?;
+        let info = format!(
+            "{} dictionary of bedic, {} entries.\nhttps://github.com/idzhonev/bedic_data-utf8\n",
+            description(direction),
+            self.dictionaries.get(direction).len()
+        );
+        self.text(&info)?;
+        self.status(250, "ok")
+    }
+
+    fn show_server(&mut self) -> io::Result<()> {
+        self.status(114, "server information follows")?;
+        self.text(&format!(
+            "rbedic {}, Bulgarian-English two-way dictionary\n",
+            env!("CARGO_PKG_VERSION")
+        ))?;
+        self.status(250, "ok")
+    }
+
+    fn help(&mut self) -> io::Result<()> {
+        self.status(113, "help text follows")?;
+        self.lines(&[
+            "DEFINE database word         -- look up word in database".to_string(),
+            "MATCH database strategy word -- match word in database using strategy".to_string(),
+            "SHOW DB                      -- list all accessible databases".to_string(),
+            "SHOW STRAT                   -- list available matching strategies".to_string(),
+            "SHOW INFO database           -- provide information about the database".to_string(),
+            "SHOW SERVER                  -- provide site-specific information".to_string(),
+            "OPTION MIME                  -- use MIME headers".to_string(),
+            "CLIENT info                  -- identify client to server".to_string(),
+            "STATUS                       -- display timing information".to_string(),
+            "HELP                         -- display this help information".to_string(),
+            "QUIT                         -- terminate connection".to_string(),
+        ])?;
+        self.status(250, "ok")
+    }
+
+    /// Databases selected by the name `database` of a DEFINE or MATCH command:
+    /// `*` and `!` select all of them. Returns `None` for unknown names.
+    fn select(&self, database: &str) -> Option<Vec<Direction>> {
+        match database {
+            "*" | "!" => Some(vec![Direction::EnBg, Direction::BgEn]),
+            _ => database.parse::<Direction>().ok().map(|direction| vec![direction]),
+        }
+    }
+
+    /// A status line: the code and its text
+    fn status(&mut self, code: u32, text: &str) -> io::Result<()> {
+        write!(self.writer, "{} {}\r\n", code, text)?;
+        self.writer.flush()
+    }
+
+    /// A text, preceded by MIME headers if requested. See `lines()`.
+    fn text(&mut self, text: &str) -> io::Result<()> {
+        let mut lines: Vec<String> = Vec::new();
+        if self.mime {
+            lines.extend(MIME_HEADERS.lines().map(|line| line.to_string()));
+            lines.push(String::new());
+        }
+        lines.extend(text.trim_end().lines().map(|line| line.to_string()));
+        self.lines(&lines)
+    }
+
+    /// Text lines terminated with a line with a single dot.
+    /// Lines starting with a dot get one more, as the protocol requires.
+    fn lines(&mut self, lines: &[String]) -> io::Result<()> {
+        for line in lines {
+            let line = line.trim_end_matches('\r');
+            if line.starts_with('.') {
+                write!(self.writer, ".{}\r\n", line)?;
+            } else {
+                write!(self.writer, "{}\r\n", line)?;
+            }
+        }
+        write!(self.writer, ".\r\n")
+    }
+}
+
+/// Entries of `dictionary` with the headword `word` (case insensitive)
+fn exact_entries(dictionary: &Dictionary, word: &str) -> Vec<DictDB> {
+    let upper_word = word.to_uppercase();
+    dictionary
+        .prefix_range(word)
+        .filter(|&index| dictionary.word(index) == upper_word)
+        .map(|index| dictionary.entry(index))
+        .collect()
+}
+
+/// Description of the database of `direction`
+fn description(direction: Direction) -> &'static str {
+    match direction {
+        Direction::EnBg => "English-Bulgarian",
+        Direction::BgEn => "Bulgarian-English",
+    }
+}
+
+/// `text` as a quoted string of the protocol
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Split a command line into words. Words are separated by spaces and may be
+/// quoted with double or single quotes; a backslash escapes the next character.
+/// Returns `None` for unterminated quotes.
+fn split_command(line: &str) -> Option<Vec<String>> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut quote_char: Option<char> = None;
+    let mut chars = line.trim_end_matches(['\r', '\n']).chars();
+    while let Some(c) = chars.next() {
+        match (c, quote_char) {
+            ('\\', _) => word.get_or_insert_with(String::new).extend(chars.next()),
+            (c, Some(q)) if c == q => quote_char = None,
+            (c, Some(_)) => word.get_or_insert_with(String::new).push(c),
+            ('"', None) | ('\'', None) => {
+                quote_char = Some(c);
+                word.get_or_insert_with(String::new);
+            }
+            (c, None) if c.is_whitespace() => words.extend(word.take()),
+            (c, None) => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    if quote_char.is_some() {
+        return None;
+    }
+    words.extend(word);
+    Some(words)
+}
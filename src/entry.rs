@@ -0,0 +1,372 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Structure of the bedic entry bodies. A body looks like
+//!
+//! ```text
+//! ABANDON
+//! [@b'and@n] v 1. изоставям, напускам; to ABANDON ship напускам кораба
+//! 2. отказвам се от
+//! n безгрижие
+//! ```
+//!
+//! that is an optional transcription in brackets, then groups of senses, each one
+//! starting with a part-of-speech marker at the beginning of a line. The senses of
+//! a group are numbered `1.`, `2.`, ... unless there is only one. A sense lists
+//! glosses separated by commas, then examples separated by semicolons.
+//!
+//! Entries may refer to other headwords with `see WORD`, `вж. WORD` or `= WORD`,
+//! see `references()`.
+
+use std::ops::Range;
+
+use regex::Regex;
+
+use database::{is_cyrillic, DictDB};
+
+/// Part-of-speech markers of the English and of the Bulgarian headwords
+const POS_MARKERS: &[&str] = &[
+    "n", "v", "a", "adv", "prep", "pron", "cj", "conj", "int", "interj", "num", "pl", "attr",
+    "aux", "art", "vi", "vt", "abbr", "pref", "suf", "м", "ж", "ср", "с", "мн", "гл", "прил",
+    "нареч", "нар", "мест", "предл", "съюз", "межд", "числ", "част",
+];
+
+/// One meaning of a headword
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Sense {
+    /// Part-of-speech marker, e.g. `n` or `гл`, as written into the entry
+    pub pos: Option<String>,
+    /// Translations of the headword
+    pub glosses: Vec<String>,
+    /// Phrases with the headword and their translations
+    pub examples: Vec<String>,
+}
+
+/// A parsed dictionary entry
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Entry {
+    /// The headword, as written into the first line of the entry
+    pub headword: String,
+    /// Pronunciation, without the brackets
+    pub transcription: Option<String>,
+    /// Meanings, in the order of the entry. Empty when the body does not fit
+    /// the grammar of the module docs, see `raw`.
+    pub senses: Vec<Sense>,
+    /// The body of the entry, without the headword line
+    pub raw: String,
+}
+
+impl Entry {
+    /// Parse the whole text of an entry, including the headword line.
+    /// Bodies which do not fit the grammar are kept in `raw` only.
+    pub fn parse(translation: &str) -> Entry {
+        let mut lines = translation.trim_end().splitn(2, '\n');
+        let headword = lines.next().unwrap_or("").trim().to_string();
+        let raw = lines.next().unwrap_or("").to_string();
+        let (transcription, body) = split_transcription(raw.trim_start());
+        let senses = parse_senses(body, headword.chars().any(is_cyrillic)).unwrap_or_else(|| {
+            debug!("Entry {:?} is kept as raw text", headword);
+            Vec::new()
+        });
+        Entry {
+            headword,
+            transcription,
+            senses,
+            raw,
+        }
+    }
+
+    /// Return true if the body was split into senses
+    pub fn is_structured(&self) -> bool {
+        !self.senses.is_empty()
+    }
+
+    /// Glosses of all senses. For an entry kept as raw text, the parts of the body
+    /// separated by `,` or `;`, without the transcription, the part-of-speech
+    /// markers and the sense numbers.
+    pub fn glosses(&self) -> Vec<String> {
+        if self.is_structured() {
+            return self
+                .senses
+                .iter()
+                .flat_map(|sense| sense.glosses.iter().cloned())
+                .collect();
+        }
+        let (_, body) = split_transcription(self.raw.trim_start());
+        body.lines()
+            .flat_map(|line| {
+                let (_, text) = split_pos(line.trim());
+                split_numbered(text).unwrap_or_else(|| vec![text])
+            })
+            .flat_map(|text| split_outside_parens(text, ';'))
+            .flat_map(|part| split_outside_parens(part, ','))
+            .map(|gloss| gloss.to_string())
+            .collect()
+    }
+}
+
+impl DictDB {
+    /// Parse the translation of the entry, see `Entry::parse()`
+    pub fn entry(&self) -> Entry {
+        Entry::parse(&self.translation)
+    }
+}
+
+/// The transcription at the start of `body`, if any, and the rest of `body`
+fn split_transcription(body: &str) -> (Option<String>, &str) {
+    if body.starts_with('[') {
+        if let Some(end) = body.find(']') {
+            let transcription = body[1..end].trim();
+            if !transcription.is_empty() && !transcription.contains('\n') {
+                return (Some(transcription.to_string()), &body[end + 1..]);
+            }
+        }
+    }
+    (None, body)
+}
+
+/// The part-of-speech marker starting `line`, if any, and the rest of `line`
+fn split_pos(line: &str) -> (Option<&str>, &str) {
+    let end = line.find(char::is_whitespace).unwrap_or(line.len());
+    let marker = line[..end].trim_end_matches('.');
+    if POS_MARKERS.contains(&marker) {
+        (Some(marker), line[end..].trim_start())
+    } else {
+        (None, line)
+    }
+}
+
+/// Texts of the numbered senses of `line`: `1. foo 2. bar` -> `["foo", "bar"]`.
+/// Returns `None` if `line` does not start with a number.
+fn split_numbered(line: &str) -> Option<Vec<&str>> {
+    lazy_static! {
+        static ref NUMBER: Regex = Regex::new(r"(?:^|\s)\d+\.(?:\s+|$)").unwrap();
+    }
+    let numbers: Vec<(usize, usize)> = NUMBER.find_iter(line).map(|m| (m.start(), m.end())).collect();
+    match numbers.first() {
+        Some(&(0, _)) => (),
+        _ => return None,
+    }
+    let texts = numbers
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, start))| {
+            let end = numbers.get(i + 1).map_or(line.len(), |&(next, _)| next);
+            line[start..end].trim()
+        })
+        .collect();
+    Some(texts)
+}
+
+/// Senses of `body`, the entry without the headword line and the transcription.
+/// Returns `None` if `body` does not fit the grammar.
+fn parse_senses(body: &str, cyrillic_headword: bool) -> Option<Vec<Sense>> {
+    let mut senses: Vec<Sense> = Vec::new();
+    // Part-of-speech marker of the current group of senses
+    let mut pos: Option<String> = None;
+    let mut group_len = 0;
+    for line in body.lines().map(|line| line.trim()).filter(|line| !line.is_empty()) {
+        let (marker, text) = split_pos(line);
+        if let Some(marker) = marker {
+            pos = Some(marker.to_string());
+            group_len = 0;
+        }
+        let texts = match split_numbered(text) {
+            Some(texts) => texts,
+            // The marker alone, the senses follow on the next lines
+            None if text.is_empty() => continue,
+            // An unnumbered sense is the only one of its group
+            None if group_len == 0 => vec![text],
+            None => return None,
+        };
+        for text in texts {
+            senses.push(parse_sense(text, pos.clone(), cyrillic_headword)?);
+            group_len += 1;
+        }
+    }
+    if senses.is_empty() {
+        None
+    } else {
+        Some(senses)
+    }
+}
+
+/// A sense from its text: the glosses, then the examples after `;`.
+/// Parts after the first `;` are examples when they are written in the alphabet
+/// of the headword, otherwise they are more glosses.
+fn parse_sense(text: &str, pos: Option<String>, cyrillic_headword: bool) -> Option<Sense> {
+    let mut sense = Sense {
+        pos,
+        glosses: Vec::new(),
+        examples: Vec::new(),
+    };
+    for (i, part) in split_outside_parens(text, ';').into_iter().enumerate() {
+        let in_headword_alphabet = part
+            .chars()
+            .any(|c| c.is_alphabetic() && is_cyrillic(c) == cyrillic_headword);
+        if i > 0 && in_headword_alphabet {
+            sense.examples.push(part.to_string());
+        } else {
+            sense.glosses.extend(
+                split_outside_parens(part, ',')
+                    .into_iter()
+                    .map(|gloss| gloss.to_string()),
+            );
+        }
+    }
+    if sense.glosses.is_empty() {
+        None
+    } else {
+        Some(sense)
+    }
+}
+
+/// Trimmed, non-empty parts of `text` separated by `separator`, which is ignored
+/// into parentheses: `книга (за четене, учене)` is a single gloss.
+fn split_outside_parens(text: &str, separator: char) -> Vec<&str> {
+    let mut parts: Vec<&str> = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth = depth.saturating_sub(1),
+            c if c == separator && depth == 0 => {
+                parts.push(&text[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    parts.push(&text[start..]);
+    parts
+        .into_iter()
+        .map(|part| part.trim())
+        .filter(|part| !part.is_empty())
+        .collect()
+}
+
+/// Byte ranges of the words referred to by `see WORD`, `cf. WORD`, `вж. WORD`
+/// or `= WORD` into `text`
+pub fn references(text: &str) -> Vec<Range<usize>> {
+    lazy_static! {
+        static ref REFERENCE: Regex =
+            Regex::new(r"(?i)(?:\b(?:see|cf\.|вж\.?)[ \t]+|(?:^|\s)=[ \t]*)(\pL[\pL'-]*)").unwrap();
+    }
+    REFERENCE
+        .captures_iter(text)
+        .filter_map(|cap| cap.get(1))
+        .map(|word| word.start()..word.end())
+        .collect()
+}
+
+/// Byte range of the word of `gloss`, without the qualifiers in parentheses
+/// before or after it: `(за кораб) напускам` -> `напускам`
+pub fn gloss_word(gloss: &str) -> Range<usize> {
+    let mut start = 0;
+    let mut end = gloss.len();
+    loop {
+        let rest = &gloss[start..end];
+        let trimmed = rest.trim_start();
+        start += rest.len() - trimmed.len();
+        match trimmed.find(')') {
+            Some(close) if trimmed.starts_with('(') => start += close + 1,
+            _ => break,
+        }
+    }
+    loop {
+        let rest = &gloss[start..end];
+        let trimmed = rest.trim_end();
+        end -= rest.len() - trimmed.len();
+        match trimmed.rfind('(') {
+            Some(open) if trimmed.ends_with(')') => end = start + open,
+            _ => break,
+        }
+    }
+    start..end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(texts: &[&str]) -> Vec<String> {
+        texts.iter().map(|text| text.to_string()).collect()
+    }
+
+    #[test]
+    fn transcription_and_numbered_senses() {
+        let entry = Entry::parse(
+            "ABANDON\n[@b'and@n] v 1. изоставям, напускам; to ABANDON ship напускам кораба\n2. отказвам се от\n",
+        );
+        assert_eq!(entry.headword, "ABANDON");
+        assert_eq!(entry.transcription, Some("@b'and@n".to_string()));
+        assert_eq!(entry.senses.len(), 2);
+        assert_eq!(entry.senses[0].pos, Some("v".to_string()));
+        assert_eq!(entry.senses[0].glosses, strings(&["изоставям", "напускам"]));
+        assert_eq!(entry.senses[0].examples, strings(&["to ABANDON ship напускам кораба"]));
+        assert_eq!(entry.senses[1].pos, Some("v".to_string()));
+        assert_eq!(entry.senses[1].glosses, strings(&["отказвам се от"]));
+        assert!(entry.is_structured());
+    }
+
+    #[test]
+    fn several_pos_groups() {
+        let entry = Entry::parse("ABANDON\nv 1. изоставям\n2. отказвам се от\nn безгрижие, (пълна) свобода");
+        let pos: Vec<Option<&str>> = entry.senses.iter().map(|sense| sense.pos.as_deref()).collect();
+        assert_eq!(pos, vec![Some("v"), Some("v"), Some("n")]);
+        assert_eq!(entry.senses[2].glosses, strings(&["безгрижие", "(пълна) свобода"]));
+        assert_eq!(entry.transcription, None);
+    }
+
+    #[test]
+    fn marker_alone_on_its_line() {
+        let entry = Entry::parse("КНИГА\nж.\n1. book\n2. volume, tome");
+        assert_eq!(entry.senses.len(), 2);
+        assert_eq!(entry.senses[0].pos, Some("ж".to_string()));
+        assert_eq!(entry.senses[1].glosses, strings(&["volume", "tome"]));
+    }
+
+    #[test]
+    fn glosses_after_semicolon_in_the_other_alphabet() {
+        // The part after `;` is in English, like the glosses of a Bulgarian headword
+        let entry = Entry::parse("КНИГА\nж book; volume (of a set); книга за четене a book to read");
+        let sense = &entry.senses[0];
+        assert_eq!(sense.glosses, strings(&["book", "volume (of a set)"]));
+        assert_eq!(sense.examples, strings(&["книга за четене a book to read"]));
+    }
+
+    #[test]
+    fn raw_fallback() {
+        // A second unnumbered sense of the same group does not fit the grammar
+        let entry = Entry::parse("ABC\nn азбука\nосновни понятия");
+        assert!(!entry.is_structured());
+        assert_eq!(entry.raw, "n азбука\nосновни понятия");
+        assert_eq!(entry.glosses(), strings(&["азбука", "основни понятия"]));
+        let entry = Entry::parse("ABC\n[eibi:'si:] n 1. азбука; буквар\n2.");
+        assert!(!entry.is_structured());
+        assert_eq!(entry.glosses(), strings(&["азбука", "буквар"]));
+        assert_eq!(Entry::parse("ABC\n").senses, Vec::new());
+        assert_eq!(Entry::parse("ABC\n1.").senses, Vec::new());
+    }
+
+    #[test]
+    fn references_of_an_entry() {
+        let text = "n see ABANDONMENT; cf. desert\nвж. КНИГА\n= BOOK";
+        let words: Vec<&str> = references(text).into_iter().map(|range| &text[range]).collect();
+        assert_eq!(words, vec!["ABANDONMENT", "desert", "КНИГА", "BOOK"]);
+        assert!(references("n seesaw, равенство = 2").is_empty());
+    }
+
+    #[test]
+    fn word_of_a_gloss() {
+        let gloss = "(за кораб) напускам (временно)";
+        assert_eq!(&gloss[gloss_word(gloss)], "напускам");
+        assert_eq!(&"книга"[gloss_word("книга")], "книга");
+    }
+}
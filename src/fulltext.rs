@@ -0,0 +1,174 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Full-text search into translations: an inverted index of the words
+//! found into translation bodies.
+
+use std::collections::HashMap;
+
+/// Number of characters shown on each side of the match into a snippet
+const SNIPPET_CONTEXT: usize = 25;
+
+/// Byte ranges of the words (runs of alphanumeric characters) into `text`
+pub fn token_spans(text: &str) -> Vec<(usize, usize)> {
+    let mut spans: Vec<(usize, usize)> = Vec::new();
+    let mut start: Option<usize> = None;
+    for (i, c) in text.char_indices() {
+        match (c.is_alphanumeric(), start) {
+            (true, None) => start = Some(i),
+            (false, Some(s)) => {
+                spans.push((s, i));
+                start = None;
+            }
+            _ => (),
+        }
+    }
+    if let Some(s) = start {
+        spans.push((s, text.len()));
+    }
+    spans
+}
+
+/// Lowercase words of `text`
+pub fn tokens(text: &str) -> Vec<String> {
+    token_spans(text)
+        .into_iter()
+        .map(|(start, end)| text[start..end].to_lowercase())
+        .collect()
+}
+
+/// The translation without the headword line
+fn body(translation: &str) -> &str {
+    match translation.find('\n') {
+        Some(i) => &translation[i + 1..],
+        None => "",
+    }
+}
+
+/// Inverted index: lowercase word -> indices of the entries containing it into their translation
+#[derive(Clone, Debug, Default)]
+pub struct InvertedIndex {
+    postings: HashMap<String, Vec<u32>>,
+}
+
+impl InvertedIndex {
+    /// Index the translations of sorted dictionary entries.
+    /// The indices returned by `find()` are the positions into `translations`.
+    pub fn new<'a, I>(translations: I) -> InvertedIndex
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut postings: HashMap<String, Vec<u32>> = HashMap::new();
+        for (index, translation) in translations.into_iter().enumerate() {
+            let index = index as u32;
+            for token in tokens(body(translation)) {
+                let posting = postings.entry(token).or_default();
+                // Indices are pushed in ascending order, so checking the last one skips duplicates
+                if posting.last() != Some(&index) {
+                    posting.push(index);
+                }
+            }
+        }
+        InvertedIndex { postings }
+    }
+
+    /// Indices of the entries whose translation contains all words of `query`
+    pub fn find(&self, query: &str) -> Vec<usize> {
+        let query_tokens = tokens(query);
+        let mut found: Option<Vec<u32>> = None;
+        for token in &query_tokens {
+            let posting = match self.postings.get(token) {
+                Some(posting) => posting,
+                None => return Vec::new(),
+            };
+            found = Some(match found {
+                None => posting.clone(),
+                Some(previous) => previous
+                    .into_iter()
+                    .filter(|index| posting.binary_search(index).is_ok())
+                    .collect(),
+            });
+        }
+        found
+            .unwrap_or_default()
+            .into_iter()
+            .map(|index| index as usize)
+            .collect()
+    }
+}
+
+/// A short part of the translation around the first word of `query`,
+/// on a single line
+pub fn snippet(translation: &str, query: &str) -> String {
+    let text = body(translation);
+    let first_token = match tokens(query).into_iter().next() {
+        Some(token) => token,
+        None => return String::new(),
+    };
+    let span = token_spans(text)
+        .into_iter()
+        .find(|&(start, end)| text[start..end].to_lowercase() == first_token);
+    let (start, end) = match span {
+        Some(span) => span,
+        None => return String::new(),
+    };
+    let before: Vec<char> = text[..start].chars().collect();
+    let after: Vec<char> = text[end..].chars().collect();
+    let mut snippet = String::new();
+    if before.len() > SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+    snippet.extend(&before[before.len().saturating_sub(SNIPPET_CONTEXT)..]);
+    snippet.push_str(&text[start..end]);
+    snippet.extend(after.iter().take(SNIPPET_CONTEXT));
+    if after.len() > SNIPPET_CONTEXT {
+        snippet.push('…');
+    }
+    snippet.split_whitespace().collect::<Vec<&str>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_of_text() {
+        assert_eq!(token_spans("a, bc  д1"), vec![(0, 1), (3, 5), (7, 10)]);
+        assert_eq!(tokens("Книга, BOOK-case"), vec!["книга", "book", "case"]);
+        assert!(tokens(" ;, ").is_empty());
+    }
+
+    #[test]
+    fn found_entries() {
+        let translations = [
+            "BOOK\nn книга; тетрадка",
+            "COPYBOOK\nn тетрадка, тетрадка за писане",
+            // The headword line is not indexed
+            "КНИГА\nж book",
+        ];
+        let index = InvertedIndex::new(translations.iter().cloned());
+        assert_eq!(index.find("тетрадка"), vec![0, 1]);
+        assert_eq!(index.find("Тетрадка писане"), vec![1]);
+        assert_eq!(index.find("книга"), vec![0]);
+        assert_eq!(index.find("book"), vec![2]);
+        assert!(index.find("тетрадка молив").is_empty());
+        assert!(index.find("").is_empty());
+    }
+
+    #[test]
+    fn snippets() {
+        assert_eq!(snippet("BOOK\nn  книга;\n тетрадка", "Тетрадка"), "n книга; тетрадка");
+        let long = format!("WORD\n{} match {}", "а".repeat(30), "б".repeat(30));
+        assert_eq!(
+            snippet(&long, "match"),
+            format!("…{} match {}…", "а".repeat(SNIPPET_CONTEXT - 1), "б".repeat(SNIPPET_CONTEXT - 1))
+        );
+        assert_eq!(snippet("BOOK\nn книга", "тетрадка"), "");
+        assert_eq!(snippet("BOOK\nn книга", ""), "");
+    }
+}
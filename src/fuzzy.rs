@@ -0,0 +1,149 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Typo tolerant search: a BK-tree of headwords ordered by edit distance.
+
+use std::cmp;
+
+/// Levenshtein distance between `a` and `b`:
+/// the number of inserted, deleted or substituted characters.
+/// It is a metric, as required by the BK-tree.
+pub fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    if a.is_empty() {
+        return b.len();
+    }
+    if b.is_empty() {
+        return a.len();
+    }
+    // Two rows of the distance matrix: i - 1 and i
+    let mut previous: Vec<usize> = (0..b.len() + 1).collect();
+    let mut current: Vec<usize> = vec![0; b.len() + 1];
+    for i in 1..a.len() + 1 {
+        current[0] = i;
+        for j in 1..b.len() + 1 {
+            let cost = if a[i - 1] == b[j - 1] { 0 } else { 1 };
+            current[j] = cmp::min(
+                cmp::min(previous[j] + 1, current[j - 1] + 1),
+                previous[j - 1] + cost,
+            );
+        }
+        previous.copy_from_slice(&current);
+    }
+    previous[b.len()]
+}
+
+/// Maximal distance allowed for a query: longer words tolerate more typos
+pub fn max_distance(query: &str) -> usize {
+    match query.chars().count() {
+        0..=4 => 1,
+        5..=8 => 2,
+        _ => 3,
+    }
+}
+
+#[derive(Clone, Debug)]
+struct BkNode {
+    /// Index of the entry into the dictionary
+    index: usize,
+    /// Pairs of (distance to this node, child node)
+    children: Vec<(usize, usize)>,
+}
+
+/// BK-tree over the words of sorted dictionary entries.
+/// The tree keeps indices only, so the same words have to be passed to `find()`.
+#[derive(Clone, Debug, Default)]
+pub struct BkTree {
+    nodes: Vec<BkNode>,
+}
+
+impl BkTree {
+    /// The tree of `len` entries, `word` gives the word of the entry at an index
+    pub fn new<'a, F>(len: usize, word: F) -> BkTree
+    where
+        F: Fn(usize) -> &'a str,
+    {
+        let mut bk_tree = BkTree {
+            nodes: Vec::with_capacity(len),
+        };
+        for index in 0..len {
+            bk_tree.insert(&word, index);
+        }
+        bk_tree
+    }
+
+    fn insert<'a, F>(&mut self, word_of: &F, index: usize)
+    where
+        F: Fn(usize) -> &'a str,
+    {
+        if self.nodes.is_empty() {
+            self.nodes.push(BkNode {
+                index,
+                children: Vec::new(),
+            });
+            return;
+        }
+        let word = word_of(index);
+        let mut node = 0;
+        loop {
+            let node_distance = distance(word_of(self.nodes[node].index), word);
+            if node_distance == 0 {
+                // Duplicated word
+                return;
+            }
+            let child = self.nodes[node]
+                .children
+                .iter()
+                .find(|&&(child_distance, _)| child_distance == node_distance)
+                .map(|&(_, child)| child);
+            match child {
+                Some(child) => node = child,
+                None => {
+                    let new_node = self.nodes.len();
+                    self.nodes.push(BkNode {
+                        index,
+                        children: Vec::new(),
+                    });
+                    self.nodes[node].children.push((node_distance, new_node));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Find the entries within `max_distance` from `query`.
+    /// Returns pairs of (distance, index of entry), sorted by distance and word.
+    pub fn find<'a, F>(&self, word: F, query: &str, max_distance: usize) -> Vec<(usize, usize)>
+    where
+        F: Fn(usize) -> &'a str,
+    {
+        let mut found: Vec<(usize, usize)> = Vec::new();
+        if self.nodes.is_empty() {
+            return found;
+        }
+        let mut stack: Vec<usize> = vec![0];
+        while let Some(node) = stack.pop() {
+            let bk_node = &self.nodes[node];
+            let node_distance = distance(word(bk_node.index), query);
+            if node_distance <= max_distance {
+                found.push((node_distance, bk_node.index));
+            }
+            for &(child_distance, child) in &bk_node.children {
+                if child_distance + max_distance >= node_distance
+                    && child_distance <= node_distance + max_distance
+                {
+                    stack.push(child);
+                }
+            }
+        }
+        // Entries are sorted by word, so sorting indices keeps the alphabetical order
+        found.sort();
+        found
+    }
+}
@@ -0,0 +1,563 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! The history file, e.g. `~/new_words.txt`, where the words added with the Add button
+//! are recorded. The file is in the JSON Lines format: a header with the format version,
+//! then one `HistoryRecord` per line:
+//!
+//! ```text
+//! {"format":"rbedic-history","version":1}
+//! {"headword":"BOOK","direction":"en-bg","translation":"BOOK\nn книга","added":"2018-06-01T12:00:00Z","lookups":2,"note":""}
+//! ```
+//!
+//! Files of older versions, where each entry follows a line of 78 dashes, are
+//! converted when they are read, keeping a copy of the original file. Files
+//! starting with anything else are not history files and are never written.
+//!
+//! Several instances of rbedic may share the file: it is locked while it is read or
+//! written, new records are appended with a single write and the file is replaced
+//! by renaming a complete copy.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::prelude::*;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use database::{parse_history, DictDB, Direction};
+
+/// Line before each entry of the history files of rbedic 0.2 and older
+pub const ENTRY_DELIMITER: &str =
+    "------------------------------------------------------------------------------";
+
+/// Value of `format` into the header line
+const FORMAT_NAME: &str = "rbedic-history";
+/// Version of the format written by this rbedic
+pub const FORMAT_VERSION: u32 = 1;
+
+/// What a history file holds
+enum Content {
+    Empty,
+    Records(Vec<HistoryRecord>),
+    /// Entries of the old format, which is converted when the file is written
+    OldFormat(Vec<HistoryRecord>),
+}
+
+/// First line of the file
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// A word of the history file
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HistoryRecord {
+    pub headword: String,
+    #[serde(with = "direction_name")]
+    pub direction: Direction,
+    /// The whole text of the entry, including the headword line
+    pub translation: String,
+    /// When the word was added, e.g. `2018-06-01T12:00:00Z`.
+    /// `None` for the words converted from the old format.
+    #[serde(default)]
+    pub added: Option<String>,
+    /// Number of times the word was looked up after it was added
+    #[serde(default)]
+    pub lookups: u32,
+    /// Personal note of the user
+    #[serde(default)]
+    pub note: String,
+}
+
+impl HistoryRecord {
+    /// A record of the entry `translation` (the headword line and the body) of the
+    /// dictionary of `direction`, added now
+    pub fn new(translation: &str, direction: Direction) -> HistoryRecord {
+        let headword = translation.lines().next().unwrap_or("").trim().to_string();
+        HistoryRecord {
+            direction,
+            headword,
+            translation: translation.trim_end().to_string(),
+            added: Some(timestamp(SystemTime::now())),
+            lookups: 0,
+            note: String::new(),
+        }
+    }
+
+    /// Return true if the record is of `headword` in `direction`
+    pub fn is_of(&self, headword: &str, direction: Direction) -> bool {
+        self.headword == headword && self.direction == direction
+    }
+
+    pub fn to_dict_db(&self) -> DictDB {
+        DictDB {
+            word: self.headword.clone(),
+            translation: self.translation.clone(),
+            direction: self.direction,
+        }
+    }
+
+    /// The record as a line of the history file, without the line break
+    pub fn to_json_line(&self) -> String {
+        // Serializing strings and numbers can not fail
+        serde_json::to_string(self).unwrap_or_default()
+    }
+}
+
+/// `en-bg` or `bg-en` into the file, as into the HTTP API
+pub(crate) mod direction_name {
+    use database::Direction;
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(direction: &Direction, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&direction.id().replace('_', "-"))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Direction, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        name.parse().map_err(D::Error::custom)
+    }
+}
+
+/// The history file at a path
+#[derive(Clone, Debug)]
+pub struct HistoryFile {
+    path: PathBuf,
+}
+
+impl HistoryFile {
+    pub fn new<P: Into<PathBuf>>(path: P) -> HistoryFile {
+        HistoryFile { path: path.into() }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Read the records, in the order of the file. A missing file has no records.
+    /// A file of the old format is converted, see `backup()`.
+    pub fn load(&self) -> io::Result<Vec<HistoryRecord>> {
+        let mut file = match open_locked(&self.path, false)? {
+            Some(file) => file,
+            None => return Ok(Vec::new()),
+        };
+        match self.read(&mut file)? {
+            Content::Empty => Ok(Vec::new()),
+            Content::Records(records) => Ok(records),
+            Content::OldFormat(records) => {
+                self.backup()?;
+                self.write_records(&records)?;
+                Ok(records)
+            }
+        }
+    }
+
+    /// Append `record`, creating the file and its directory if needed.
+    /// Writers of other processes wait for the lock.
+    pub fn append(&self, record: &HistoryRecord) -> io::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            if !parent.as_os_str().is_empty() {
+                fs::create_dir_all(parent)?;
+            }
+        }
+        let mut file = match open_locked(&self.path, true)? {
+            Some(file) => file,
+            None => return Err(io::Error::new(ErrorKind::NotFound, "history file can not be created")),
+        };
+        let mut text = String::new();
+        match self.read(&mut file)? {
+            Content::Empty => {
+                text.push_str(&header_line());
+                text.push('\n');
+            }
+            Content::Records(_) => (),
+            Content::OldFormat(mut records) => {
+                self.backup()?;
+                records.push(record.clone());
+                return self.write_records(&records);
+            }
+        }
+        text.push_str(&record.to_json_line());
+        text.push('\n');
+        file.write_all(text.as_bytes())?;
+        // The lock is released when the file is closed
+        file.sync_data()
+    }
+
+    /// Read the records, change them with `change` and write them back, all under
+    /// the lock. Returns the changed records.
+    pub fn update<F: FnOnce(&mut Vec<HistoryRecord>)>(&self, change: F) -> io::Result<Vec<HistoryRecord>> {
+        // Held until the file is replaced
+        let file = open_locked(&self.path, false)?;
+        let mut records = match file {
+            Some(ref file) => match self.read(&mut file.try_clone()?)? {
+                Content::Empty => Vec::new(),
+                Content::Records(records) => records,
+                Content::OldFormat(records) => {
+                    self.backup()?;
+                    records
+                }
+            },
+            None => Vec::new(),
+        };
+        change(&mut records);
+        self.write_records(&records)?;
+        Ok(records)
+    }
+
+    /// Read the records of the locked `file`
+    fn read(&self, file: &mut File) -> io::Result<Content> {
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+        let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+        let first_line = match lines.next() {
+            None => return Ok(Content::Empty),
+            Some(line) => line,
+        };
+        let header: Header = match serde_json::from_str(first_line) {
+            Ok(header) => header,
+            // No header, the entries follow lines of dashes
+            Err(_) if first_line.trim_end() == ENTRY_DELIMITER => {
+                let records = parse_history(&content)
+                    .into_iter()
+                    .filter(|dict_db| !dict_db.word.trim().is_empty())
+                    .map(|dict_db| HistoryRecord {
+                        headword: dict_db.word,
+                        direction: dict_db.direction,
+                        translation: dict_db.translation.trim_end().to_string(),
+                        added: None,
+                        lookups: 0,
+                        note: String::new(),
+                    })
+                    .collect();
+                return Ok(Content::OldFormat(records));
+            }
+            // Left as it is, it may be another file given by mistake
+            Err(err) => return Err(invalid_data(&format!("not a history file of rbedic: {}", err))),
+        };
+        if header.format != FORMAT_NAME {
+            return Err(invalid_data("not a history file of rbedic"));
+        }
+        if header.version > FORMAT_VERSION {
+            return Err(invalid_data(&format!(
+                "history file of format version {}, this rbedic supports up to version {}",
+                header.version, FORMAT_VERSION
+            )));
+        }
+        let mut records: Vec<HistoryRecord> = Vec::new();
+        for (number, line) in lines.enumerate() {
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(err) => warn!("History file {:?}, record {}: {}", self.path, number + 1, err),
+            }
+        }
+        Ok(Content::Records(records))
+    }
+
+    /// Keep a copy of the file of the old format before converting it, as
+    /// `new_words.txt.bak` (or `.bak.1`, ... if there is one already)
+    fn backup(&self) -> io::Result<()> {
+        let backup_path = (0..)
+            .map(|n| {
+                let mut name = self.path.clone().into_os_string();
+                name.push(if n == 0 { ".bak".to_string() } else { format!(".bak.{}", n) });
+                PathBuf::from(name)
+            })
+            .find(|path| !path.exists())
+            .unwrap_or_default();
+        info!(
+            "Converting history file {:?} to format version {}, the original is kept as {:?}",
+            self.path, FORMAT_VERSION, backup_path
+        );
+        fs::copy(&self.path, &backup_path).map(|_| ())
+    }
+
+    /// Replace the file with `records`, see `replace_file()`
+    fn write_records(&self, records: &[HistoryRecord]) -> io::Result<()> {
+        let mut text = header_line();
+        text.push('\n');
+        for record in records {
+            text.push_str(&record.to_json_line());
+            text.push('\n');
+        }
+        replace_file(&self.path, &text)
+    }
+}
+
+/// Open the file at `path` locked until it is closed, for appending if it is `create`d.
+/// Returns `None` if the file is missing and not `create`d.
+pub(crate) fn open_locked(path: &Path, create: bool) -> io::Result<Option<File>> {
+    loop {
+        let file = match OpenOptions::new().read(true).append(create).create(create).open(path) {
+            Ok(file) => file,
+            Err(ref err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        lock(&file)?;
+        // Another process may have replaced the file while waiting for the lock
+        if is_same_file(&file, path)? {
+            return Ok(Some(file));
+        }
+        debug!("File {:?} was replaced, open it again", path);
+    }
+}
+
+/// Replace the file at `path` with `text`: write it to a new temporary file of the
+/// same directory, then rename it, so readers see either the old or the new file.
+/// The caller holds the lock of the file, see `open_locked()`, until
+/// this returns.
+pub(crate) fn replace_file(path: &Path, text: &str) -> io::Result<()> {
+    let (temp_path, mut temp_file) = create_temp_file(path)?;
+    let result = temp_file
+        .write_all(text.as_bytes())
+        .and_then(|_| temp_file.sync_all())
+        .and_then(|_| fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    sync_dir(path)
+}
+
+/// A new file next to `path`, e.g. `.new_words.txt.1234.0.tmp`, named after
+/// the process so that other instances of rbedic do not write into it
+pub(crate) fn create_temp_file(path: &Path) -> io::Result<(PathBuf, File)> {
+    static TEMP_FILES: AtomicUsize = AtomicUsize::new(0);
+
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    loop {
+        let number = TEMP_FILES.fetch_add(1, Ordering::SeqCst);
+        let temp_path = path.with_file_name(format!(".{}.{}.{}.tmp", file_name, process::id(), number));
+        match OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(file) => return Ok((temp_path, file)),
+            // Left by a crashed process of the same id
+            Err(ref err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// Make the rename of the file at `path` durable
+#[cfg(unix)]
+pub(crate) fn sync_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+fn header_line() -> String {
+    let header = Header {
+        format: FORMAT_NAME.to_string(),
+        version: FORMAT_VERSION,
+    };
+    serde_json::to_string(&header).unwrap_or_default()
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// `time` in UTC, e.g. `2018-06-01T12:00:00Z`
+pub fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let (days, second_of_day) = (seconds / 86_400, seconds % 86_400);
+    // Civil date from the number of days since 1970-01-01, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z % 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        second_of_day / 3600,
+        second_of_day / 60 % 60,
+        second_of_day % 60
+    )
+}
+
+/// Lock `file` exclusively until it is closed
+#[cfg(unix)]
+fn lock(file: &File) -> io::Result<()> {
+    use libc;
+    use std::os::unix::io::AsRawFd;
+
+    loop {
+        if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } == 0 {
+            return Ok(());
+        }
+        let err = io::Error::last_os_error();
+        if err.kind() != ErrorKind::Interrupted {
+            return Err(err);
+        }
+    }
+}
+
+/// Files are not locked on other systems
+#[cfg(not(unix))]
+fn lock(_file: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// Return true if the open `file` is the one at `path`
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::MetadataExt;
+
+    let opened = file.metadata()?;
+    match fs::metadata(path) {
+        Ok(current) => Ok(opened.dev() == current.dev() && opened.ino() == current.ino()),
+        Err(ref err) if err.kind() == ErrorKind::NotFound => Ok(false),
+        Err(err) => Err(err),
+    }
+}
+
+#[cfg(not(unix))]
+fn is_same_file(_file: &File, _path: &Path) -> io::Result<bool> {
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A new directory for the files of one test
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = ::std::env::temp_dir().join(format!("rbedic-history-test-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn old_format(entries: &[&str]) -> String {
+        entries
+            .iter()
+            .map(|entry| format!("{}\n{}\n", ENTRY_DELIMITER, entry))
+            .collect()
+    }
+
+    /// Names of the files into `dir`, sorted
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = fs::read_dir(dir)
+            .unwrap()
+            .map(|dir_entry| dir_entry.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn old_format_is_converted() {
+        let dir = test_dir("old");
+        let path = dir.join("new_words.txt");
+        let original = old_format(&["BOOK\nn книга", "КНИГА\nж book"]);
+        fs::write(&path, &original).unwrap();
+
+        let history_file = HistoryFile::new(&path);
+        let records = history_file.load().unwrap();
+        assert_eq!(records.len(), 2);
+        assert!(records[0].is_of("BOOK", Direction::EnBg));
+        assert_eq!(records[0].translation, "BOOK\nn книга");
+        assert_eq!(records[0].added, None);
+        assert!(records[1].is_of("КНИГА", Direction::BgEn));
+        // The original is kept, the file is rewritten in the new format
+        assert_eq!(fs::read_to_string(dir.join("new_words.txt.bak")).unwrap(), original);
+        let converted = fs::read_to_string(&path).unwrap();
+        assert!(converted.starts_with("{\"format\":\"rbedic-history\",\"version\":1}\n"));
+        assert_eq!(history_file.load().unwrap(), records);
+
+        // Another old file is converted by append(), next to the first backup
+        fs::write(&path, old_format(&["CAT\nn котка"])).unwrap();
+        history_file.append(&HistoryRecord::new("DOG\nn куче", Direction::EnBg)).unwrap();
+        let records = history_file.load().unwrap();
+        let headwords: Vec<&str> = records.iter().map(|record| record.headword.as_str()).collect();
+        assert_eq!(headwords, vec!["CAT", "DOG"]);
+        assert_eq!(file_names(&dir), vec!["new_words.txt", "new_words.txt.bak", "new_words.txt.bak.1"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn foreign_files_are_left_untouched() {
+        let dir = test_dir("foreign");
+        let path = dir.join("notes.txt");
+        let history_file = HistoryFile::new(&path);
+        let record = HistoryRecord::new("BOOK\nn книга", Direction::EnBg);
+        let foreign = [
+            "Shopping list\nbread\n".to_string(),
+            "{\"format\":\"something-else\",\"version\":1}\n".to_string(),
+            format!("{{\"format\":\"rbedic-history\",\"version\":{}}}\n", FORMAT_VERSION + 1),
+        ];
+        for content in &foreign {
+            fs::write(&path, content).unwrap();
+            assert_eq!(history_file.load().unwrap_err().kind(), ErrorKind::InvalidData);
+            assert_eq!(history_file.append(&record).unwrap_err().kind(), ErrorKind::InvalidData);
+            assert!(history_file.update(|records| records.clear()).is_err());
+            assert_eq!(&fs::read_to_string(&path).unwrap(), content);
+        }
+        assert_eq!(file_names(&dir), vec!["notes.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn append_and_update() {
+        let dir = test_dir("update");
+        let path = dir.join("words").join("new_words.txt");
+        let history_file = HistoryFile::new(&path);
+        assert!(history_file.load().unwrap().is_empty());
+
+        // The directory and the file are created
+        history_file.append(&HistoryRecord::new("BOOK\nn книга\n", Direction::EnBg)).unwrap();
+        history_file.append(&HistoryRecord::new("КНИГА\nж book", Direction::BgEn)).unwrap();
+        let records = history_file.load().unwrap();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].translation, "BOOK\nn книга");
+
+        let updated = history_file
+            .update(|records| {
+                records.retain(|record| !record.is_of("BOOK", Direction::EnBg));
+                records[0].lookups += 1;
+                records[0].note = "a note".to_string();
+            })
+            .unwrap();
+        assert_eq!(updated.len(), 1);
+        assert_eq!(history_file.load().unwrap(), updated);
+        assert_eq!(updated[0].lookups, 1);
+        assert_eq!(updated[0].note, "a note");
+        // replace_file() leaves no temporary files
+        assert_eq!(file_names(path.parent().unwrap()), vec!["new_words.txt"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(UNIX_EPOCH), "1970-01-01T00:00:00Z");
+        let time = UNIX_EPOCH + ::std::time::Duration::from_secs(1_527_854_400);
+        assert_eq!(timestamp(time), "2018-06-01T12:00:00Z");
+        let time = UNIX_EPOCH + ::std::time::Duration::from_secs(951_825_599);
+        assert_eq!(timestamp(time), "2000-02-29T11:59:59Z");
+    }
+}
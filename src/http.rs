@@ -0,0 +1,344 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Lookup API over HTTP, answering with JSON:
+//!
+//! * `GET /lookup?q=abandon&dir=en-bg&limit=10`: the words starting with `q`.
+//!   `dir` is detected by the script of `q` if missing, `limit=0` returns all the words.
+//! * `GET /entry/abandon`: the entry of one word, `404` if there is none.
+//!
+//! Web pages of other sites may call the API only if the server is bound with `cors`.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use serde_json;
+
+use database::{DictDB, Dictionaries, Direction};
+
+/// Clients which send no complete request within this time are disconnected
+const READ_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Number of clients served at once, the next ones are answered with `503`
+pub const MAX_CONNECTIONS: usize = 32;
+
+/// Longest request line and headers accepted, together. Longer ones are answered with `431`.
+pub const MAX_HEADER_LEN: usize = 8192;
+
+/// An entry, as returned by the API
+#[derive(Serialize)]
+struct EntryJson<'a> {
+    headword: &'a str,
+    /// `en-bg` or `bg-en`
+    direction: String,
+    translation: &'a str,
+}
+
+impl<'a> EntryJson<'a> {
+    fn new(entry: &'a DictDB) -> EntryJson<'a> {
+        EntryJson {
+            headword: &entry.word,
+            direction: direction_name(entry.direction),
+            translation: entry.translation.trim_end(),
+        }
+    }
+}
+
+/// Result of `/lookup`
+#[derive(Serialize)]
+struct LookupJson<'a> {
+    query: &'a str,
+    direction: String,
+    /// True if `query` itself is a headword
+    exact: bool,
+    /// Number of words starting with `query`, `entries` holds up to `limit` of them
+    total: usize,
+    entries: Vec<EntryJson<'a>>,
+}
+
+#[derive(Serialize)]
+struct ErrorJson<'a> {
+    error: &'a str,
+}
+
+/// An HTTP response: the status and the JSON body
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json<T: ::serde::Serialize>(status: u16, value: &T) -> Response {
+        Response {
+            status,
+            // Serializing the structs above can not fail
+            body: serde_json::to_string(value).unwrap_or_default(),
+        }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, &ErrorJson { error: message })
+    }
+
+    fn reason(&self) -> &'static str {
+        match self.status {
+            200 => "OK",
+            400 => "Bad Request",
+            404 => "Not Found",
+            405 => "Method Not Allowed",
+            431 => "Request Header Fields Too Large",
+            503 => "Service Unavailable",
+            _ => "Internal Server Error",
+        }
+    }
+}
+
+/// HTTP server of the lookup API
+pub struct HttpServer {
+    listener: TcpListener,
+    dictionaries: Arc<Dictionaries>,
+    /// Number of entries returned by `/lookup` without `limit`
+    limit: usize,
+    /// Send `Access-Control-Allow-Origin: *`, letting web pages of any site use the API
+    cors: bool,
+}
+
+impl HttpServer {
+    /// Listen on `addr`, e.g. `127.0.0.1:8080` to accept local clients only.
+    /// `limit` is the default number of entries returned by `/lookup`. With `cors`,
+    /// web pages of any site may call the API.
+    pub fn bind<A: ToSocketAddrs>(
+        addr: A,
+        dictionaries: Arc<Dictionaries>,
+        limit: usize,
+        cors: bool,
+    ) -> io::Result<HttpServer> {
+        let listener = TcpListener::bind(addr)?;
+        Ok(HttpServer {
+            listener,
+            dictionaries,
+            limit,
+            cors,
+        })
+    }
+
+    /// Address the server listens on
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.listener.local_addr()
+    }
+
+    /// Serve the clients, each one on its own thread, up to `MAX_CONNECTIONS` at once.
+    /// Returns only on errors of the listener.
+    pub fn run(&self) -> io::Result<()> {
+        info!("HTTP server listening on {}", self.local_addr()?);
+        let active = Arc::new(AtomicUsize::new(0));
+        for stream in self.listener.incoming() {
+            let stream = stream?;
+            if active.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                active.fetch_sub(1, Ordering::SeqCst);
+                warn!(
+                    "HTTP connection from {:?}: {} clients already, refused",
+                    stream.peer_addr().ok(),
+                    MAX_CONNECTIONS
+                );
+                let _ = write_response(stream, &Response::error(503, "too many clients"), self.cors);
+                continue;
+            }
+            let dictionaries = self.dictionaries.clone();
+            let limit = self.limit;
+            let cors = self.cors;
+            let active = active.clone();
+            thread::spawn(move || {
+                let peer = stream.peer_addr().ok();
+                if let Err(err) = serve_client(stream, &dictionaries, limit, cors) {
+                    warn!("HTTP connection from {:?}: {}", peer, err);
+                }
+                active.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Answer one request. The connection is closed afterwards.
+fn serve_client(stream: TcpStream, dictionaries: &Dictionaries, limit: usize, cors: bool) -> io::Result<()> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let request_line = read_head(BufReader::new(stream.try_clone()?))?;
+    let response = match request_line {
+        Some(request_line) => {
+            debug!("HTTP request {:?}", request_line.trim_end());
+            match request_line.split_whitespace().collect::<Vec<&str>>().as_slice() {
+                ["GET", target, _] => route(dictionaries, target, limit),
+                [_, _, _] => Response::error(405, "only GET is supported"),
+                _ => Response::error(400, "malformed request"),
+            }
+        }
+        None => Response::error(431, "request headers too large"),
+    };
+    write_response(stream, &response, cors)
+}
+
+/// Read the request line and skip the headers, up to the empty line.
+/// Returns `None` if they are longer than `MAX_HEADER_LEN` together.
+fn read_head<R: BufRead>(reader: R) -> io::Result<Option<String>> {
+    let mut head = reader.take(MAX_HEADER_LEN as u64);
+    let mut request_line = String::new();
+    head.read_line(&mut request_line)?;
+    // Skip the headers, up to the empty line or the end of the stream
+    let mut header = request_line.clone();
+    while header.ends_with('\n') {
+        header.clear();
+        if head.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+    }
+    let empty_line = header.ends_with('\n') && header.trim().is_empty();
+    if head.limit() == 0 && !empty_line {
+        return Ok(None);
+    }
+    Ok(Some(request_line))
+}
+
+/// Send `response`, with the CORS header if `cors` is set
+fn write_response(mut writer: TcpStream, response: &Response, cors: bool) -> io::Result<()> {
+    write!(
+        writer,
+        "HTTP/1.1 {} {}\r\n\
+         Content-Type: application/json; charset=utf-8\r\n\
+         Content-Length: {}\r\n{}\
+         Connection: close\r\n\r\n{}",
+        response.status,
+        response.reason(),
+        response.body.len(),
+        if cors { "Access-Control-Allow-Origin: *\r\n" } else { "" },
+        response.body
+    )?;
+    writer.flush()
+}
+
+/// Response to a request for `target`, the path with the query string
+fn route(dictionaries: &Dictionaries, target: &str, default_limit: usize) -> Response {
+    let (path, query_string) = match target.find('?') {
+        Some(position) => (&target[..position], &target[position + 1..]),
+        None => (target, ""),
+    };
+    let params: Vec<(String, String)> = query_string
+        .split('&')
+        .filter(|param| !param.is_empty())
+        .map(|param| {
+            let mut key_value = param.splitn(2, '=');
+            let key = percent_decode(key_value.next().unwrap_or(""));
+            let value = percent_decode(key_value.next().unwrap_or(""));
+            (key, value)
+        })
+        .collect();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let direction = match param("dir").map(|dir| dir.parse::<Direction>()) {
+        Some(Ok(direction)) => Some(direction),
+        Some(Err(err)) => return Response::error(400, &err),
+        None => None,
+    };
+    if path == "/lookup" {
+        let query = match param("q") {
+            Some(query) if !query.trim().is_empty() => query.trim(),
+            _ => return Response::error(400, "missing parameter q"),
+        };
+        // 0 means no limit, like `--limit 0`
+        let limit = match param("limit").map(|limit| limit.parse::<usize>()) {
+            Some(Ok(0)) => usize::MAX,
+            Some(Ok(limit)) => limit,
+            Some(Err(_)) => return Response::error(400, "limit is not a number"),
+            None => default_limit,
+        };
+        lookup(dictionaries, query, direction, limit)
+    } else if let Some(word) = path.strip_prefix("/entry/") {
+        let word = percent_decode(word);
+        if word.trim().is_empty() {
+            return Response::error(400, "missing word");
+        }
+        entry(dictionaries, word.trim(), direction)
+    } else {
+        Response::error(404, "unknown path, use /lookup?q=WORD or /entry/WORD")
+    }
+}
+
+/// Up to `limit` words starting with `query` (case insensitive), only those are copied
+fn lookup(dictionaries: &Dictionaries, query: &str, direction: Option<Direction>, limit: usize) -> Response {
+    let direction = direction.unwrap_or_else(|| Direction::detect(query));
+    let dictionary = dictionaries.get(direction);
+    let range = dictionary.prefix_range(query);
+    let entries: Vec<DictDB> = range.clone().take(limit).map(|index| dictionary.entry(index)).collect();
+    Response::json(
+        200,
+        &LookupJson {
+            query,
+            direction: direction_name(direction),
+            exact: dictionary.contains(query),
+            total: range.len(),
+            entries: entries.iter().map(EntryJson::new).collect(),
+        },
+    )
+}
+
+/// The entry of `word` (case insensitive)
+fn entry(dictionaries: &Dictionaries, word: &str, direction: Option<Direction>) -> Response {
+    let dictionary = dictionaries.get(direction.unwrap_or_else(|| Direction::detect(word)));
+    let upper_word = word.to_uppercase();
+    let found = dictionary
+        .prefix_range(word)
+        .find(|&index| dictionary.word(index) == upper_word)
+        .map(|index| dictionary.entry(index));
+    match found {
+        Some(entry) => Response::json(200, &EntryJson::new(&entry)),
+        None => Response::error(404, "word not found"),
+    }
+}
+
+/// Name of `direction` into the API, as accepted by the `dir` parameter
+fn direction_name(direction: Direction) -> String {
+    direction.id().replace('_', "-")
+}
+
+/// Decode `%XX` escapes and `+` (a space) of a URL part.
+/// Invalid escapes are kept as they are.
+fn percent_decode(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut decoded: Vec<u8> = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        // Two hex digits: from_str_radix() would accept a sign too, e.g. `+1`
+        let hex = text
+            .get(i + 1..i + 3)
+            .filter(|hex| hex.bytes().all(|byte| byte.is_ascii_hexdigit()))
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (b'+', _) => {
+                decoded.push(b' ');
+                i += 1;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
@@ -0,0 +1,431 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Precompiled binary index of a dictionary file, for fast startup.
+//!
+//! The index keeps the sorted entries of one `.dat` file and is loaded with mmap,
+//! so words and translations are looked up without copying. Opening it reads the
+//! header only: it is versioned and stamped with the size and modification time of
+//! the source file, so a stale index is detected and rebuilt. The hashes of the
+//! source file and of the body are checked by `CompiledIndex::verify()` only,
+//! see `rbedic index --check`.
+//!
+//! Layout (little endian):
+//!
+//! ```text
+//!  0  magic "RBEDIDX\0"
+//!  8  u32 format version         12  u32 direction (0 EN→BG, 1 BG→EN)
+//! 16  u64 source size            24  u64 source mtime, seconds
+//! 32  u32 source mtime, nanos    36  u32 source encoding (0 UTF-8, 1 cp1251, 2 KOI8-R)
+//! 40  u64 FNV-1a hash of source  48  u64 FNV-1a hash of the body
+//! 56  u64 number of entries
+//! 64  body: per entry u32 word offset, u32 word length,
+//!           u32 translation offset, u32 translation length;
+//!     followed by the strings, offsets are relative to them
+//! ```
+
+use std::fs::{self, File};
+use std::io::{self, BufWriter, ErrorKind, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::str;
+use std::time::UNIX_EPOCH;
+
+use dirs;
+use memmap::Mmap;
+
+use charset::Charset;
+use database::{DictDB, Direction};
+use fuzzy;
+use history;
+
+const MAGIC: &[u8; 8] = b"RBEDIDX\0";
+/// Version of the index format, increment it on each change of the layout
+pub const INDEX_VERSION: u32 = 2;
+const HEADER_LEN: usize = 64;
+const RECORD_LEN: usize = 16;
+
+/// 64-bit FNV-1a hash
+pub fn fnv1a(data: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in data {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// Directory of the compiled indices: `$XDG_CACHE_HOME/rbedic`
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("rbedic"))
+}
+
+/// Path of the compiled index of the dictionary file `source_path`
+pub fn cache_path(source_path: &Path) -> Option<PathBuf> {
+    let file_name = source_path.file_name()?;
+    let mut index_name = file_name.to_os_string();
+    index_name.push(".idx");
+    cache_dir().map(|dir| dir.join(index_name))
+}
+
+/// Size, modification time and hash of a source file
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SourceStamp {
+    len: u64,
+    mtime_secs: u64,
+    mtime_nanos: u32,
+    hash: u64,
+}
+
+/// Size and modification time of `path`
+fn file_times(path: &Path) -> io::Result<(u64, u64, u32)> {
+    let metadata = fs::metadata(path)?;
+    let mtime = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+    Ok((metadata.len(), mtime.as_secs(), mtime.subsec_nanos()))
+}
+
+fn direction_code(direction: Direction) -> u32 {
+    match direction {
+        Direction::EnBg => 0,
+        Direction::BgEn => 1,
+    }
+}
+
+fn charset_code(charset: Charset) -> u32 {
+    match charset {
+        Charset::Utf8 => 0,
+        Charset::Cp1251 => 1,
+        Charset::Koi8R => 2,
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    let mut bytes = [0u8; 4];
+    bytes.copy_from_slice(&buf[offset..offset + 4]);
+    u32::from_le_bytes(bytes)
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0u8; 8];
+    bytes.copy_from_slice(&buf[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Write the index of sorted `entries`, parsed from `source_data` read from `source_path`
+/// and decoded from `charset`.
+/// The index is written into a temporary file, which is renamed to `index_path`.
+pub fn write(
+    index_path: &Path,
+    direction: Direction,
+    source_path: &Path,
+    source_data: &[u8],
+    charset: Charset,
+    entries: &[DictDB],
+) -> io::Result<()> {
+    let (len, mtime_secs, mtime_nanos) = file_times(source_path)?;
+    let stamp = SourceStamp {
+        len,
+        mtime_secs,
+        mtime_nanos,
+        hash: fnv1a(source_data),
+    };
+    // Body: records and strings
+    let mut records: Vec<u8> = Vec::with_capacity(entries.len() * RECORD_LEN);
+    let mut strings: Vec<u8> = Vec::new();
+    for entry in entries {
+        for text in &[&entry.word, &entry.translation] {
+            if strings.len() + text.len() > u32::MAX as usize {
+                return Err(invalid_data("dictionary too large for the index"));
+            }
+            records.extend_from_slice(&(strings.len() as u32).to_le_bytes());
+            records.extend_from_slice(&(text.len() as u32).to_le_bytes());
+            strings.extend_from_slice(text.as_bytes());
+        }
+    }
+    let body_hash = fnv1a(&[&records[..], &strings[..]].concat());
+
+    let mut header: Vec<u8> = Vec::with_capacity(HEADER_LEN);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&INDEX_VERSION.to_le_bytes());
+    header.extend_from_slice(&direction_code(direction).to_le_bytes());
+    header.extend_from_slice(&stamp.len.to_le_bytes());
+    header.extend_from_slice(&stamp.mtime_secs.to_le_bytes());
+    header.extend_from_slice(&stamp.mtime_nanos.to_le_bytes());
+    header.extend_from_slice(&charset_code(charset).to_le_bytes());
+    header.extend_from_slice(&stamp.hash.to_le_bytes());
+    header.extend_from_slice(&body_hash.to_le_bytes());
+    header.extend_from_slice(&(entries.len() as u64).to_le_bytes());
+
+    if let Some(dir) = index_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // A temporary file of this process, so that concurrent writers do not mix their
+    // indices, synced before the rename, so that a crash leaves no truncated index
+    let (temp_path, temp_file) = history::create_temp_file(index_path)?;
+    let mut writer = BufWriter::new(temp_file);
+    let result = writer
+        .write_all(&header)
+        .and_then(|_| writer.write_all(&records))
+        .and_then(|_| writer.write_all(&strings))
+        .and_then(|_| writer.into_inner().map_err(|err| err.into_error()))
+        .and_then(|temp_file| temp_file.sync_all())
+        .and_then(|_| fs::rename(&temp_path, index_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result?;
+    history::sync_dir(index_path)?;
+    info!("Wrote index {:?} with {} entries", index_path, entries.len());
+    Ok(())
+}
+
+/// A word and its translation borrowed from a `CompiledIndex`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IndexEntry<'a> {
+    pub word: &'a str,
+    pub translation: &'a str,
+}
+
+/// Memory mapped index of one dictionary file
+#[derive(Debug)]
+pub struct CompiledIndex {
+    mmap: Mmap,
+    direction: Direction,
+    count: usize,
+    strings_start: usize,
+}
+
+impl CompiledIndex {
+    /// Open the index at `index_path` of the dictionary file `source_path`.
+    /// Fails with `ErrorKind::InvalidData` if the index is not an index of this version,
+    /// the size or the modification time of the source file changed, or the source was
+    /// decoded from another `encoding` (any encoding is accepted if it is `None`).
+    /// Only the header is read, see `verify()` for the content.
+    pub fn open(
+        index_path: &Path,
+        source_path: &Path,
+        direction: Direction,
+        encoding: Option<Charset>,
+    ) -> io::Result<CompiledIndex> {
+        let file = File::open(index_path)?;
+        // The index is only replaced by rename, so the mapped file is not modified meanwhile
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < HEADER_LEN || &mmap[0..8] != MAGIC {
+            return Err(invalid_data("not an rbedic index"));
+        }
+        if read_u32(&mmap, 8) != INDEX_VERSION {
+            return Err(invalid_data("another index version"));
+        }
+        if read_u32(&mmap, 12) != direction_code(direction) {
+            return Err(invalid_data("index of another direction"));
+        }
+        if let Some(charset) = encoding {
+            if read_u32(&mmap, 36) != charset_code(charset) {
+                return Err(invalid_data("source decoded from another encoding"));
+            }
+        }
+        let (len, mtime_secs, mtime_nanos) = file_times(source_path)?;
+        if read_u64(&mmap, 16) != len {
+            return Err(invalid_data("stale index: source size differs"));
+        }
+        // A touched or copied source file is parsed again, once
+        if read_u64(&mmap, 24) != mtime_secs || read_u32(&mmap, 32) != mtime_nanos {
+            return Err(invalid_data("stale index: source modification time differs"));
+        }
+        let count = read_u64(&mmap, 56) as usize;
+        let strings_start = count
+            .checked_mul(RECORD_LEN)
+            .and_then(|records_len| records_len.checked_add(HEADER_LEN))
+            .filter(|&strings_start| strings_start <= mmap.len())
+            .ok_or_else(|| invalid_data("corrupted index: too many entries"))?;
+        debug!("Opened index {:?} with {} entries", index_path, count);
+        Ok(CompiledIndex {
+            mmap,
+            direction,
+            count,
+            strings_start,
+        })
+    }
+
+    /// Check the whole content: the checksum of the body, the strings and the hash
+    /// of the source file `source_path`. Reads both files entirely.
+    pub fn verify(&self, source_path: &Path) -> io::Result<()> {
+        if read_u64(&self.mmap, 48) != fnv1a(&self.mmap[HEADER_LEN..]) {
+            return Err(invalid_data("corrupted index: checksum differs"));
+        }
+        for i in 0..self.count {
+            for field in 0..2 {
+                if self.text(i, field).is_none() {
+                    return Err(invalid_data("corrupted index: invalid string"));
+                }
+            }
+        }
+        if read_u64(&self.mmap, 40) != fnv1a(&fs::read(source_path)?) {
+            return Err(invalid_data("stale index: source hash differs"));
+        }
+        Ok(())
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn len(&self) -> usize {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Word (`field` 0) or translation (`field` 1) of entry `i`,
+    /// `None` if the index is corrupted
+    fn text(&self, i: usize, field: usize) -> Option<&str> {
+        let record = HEADER_LEN + i * RECORD_LEN + field * 8;
+        let offset = self.strings_start + read_u32(&self.mmap, record) as usize;
+        let len = read_u32(&self.mmap, record + 4) as usize;
+        self.mmap
+            .get(offset..offset + len)
+            .and_then(|bytes| str::from_utf8(bytes).ok())
+    }
+
+    /// Word of entry `i`, empty if the index is corrupted
+    pub fn word(&self, i: usize) -> &str {
+        self.text(i, 0).unwrap_or("")
+    }
+
+    pub fn translation(&self, i: usize) -> &str {
+        self.text(i, 1).unwrap_or("")
+    }
+
+    pub fn entry<'a>(&'a self, i: usize) -> IndexEntry<'a> {
+        IndexEntry {
+            word: self.word(i),
+            translation: self.translation(i),
+        }
+    }
+
+    /// Index of the first entry for which `pred` is false
+    fn partition_point<F>(&self, mut pred: F) -> usize
+    where
+        F: FnMut(&str) -> bool,
+    {
+        let mut base = 0usize;
+        let mut size = self.count;
+        while size > 0 {
+            let half = size / 2;
+            let mid = base + half;
+            if pred(self.word(mid)) {
+                base = mid + 1;
+                size -= half + 1;
+            } else {
+                size = half;
+            }
+        }
+        base
+    }
+
+    /// Range of the entries with words starting with `searched_txt` (case insensitive)
+    pub fn prefix_range(&self, searched_txt: &str) -> Range<usize> {
+        let prefix = searched_txt.to_uppercase();
+        let lower_bound = self.partition_point(|word| word < prefix.as_str());
+        let upper_bound =
+            self.partition_point(|word| word < prefix.as_str() || word.starts_with(prefix.as_str()));
+        lower_bound..upper_bound
+    }
+
+    /// Search for words starting with `searched_txt` without copying them,
+    /// the same way as `DictDB::search()`.
+    pub fn search<'a>(&'a self, searched_txt: &str) -> Result<Vec<IndexEntry<'a>>, Vec<IndexEntry<'a>>> {
+        let range = self.prefix_range(searched_txt);
+        let exact_match =
+            range.start < range.end && self.word(range.start) == searched_txt.to_uppercase();
+        let entries: Vec<IndexEntry> = range.map(|i| self.entry(i)).collect();
+        if exact_match {
+            Ok(entries)
+        } else {
+            Err(entries)
+        }
+    }
+
+    /// Copy of entry `i`
+    pub fn to_dict_db(&self, i: usize) -> DictDB {
+        DictDB {
+            word: self.word(i).to_string(),
+            translation: self.translation(i).to_string(),
+            direction: self.direction,
+        }
+    }
+
+    /// Up to `limit` words similar to `searched_txt` (case insensitive), the closest
+    /// first, see `Dictionary::fuzzy_search()`. The words are compared one by one,
+    /// which is fast enough for a single query and needs no BK-tree.
+    pub fn fuzzy_search<'a>(&'a self, searched_txt: &str, limit: usize) -> Vec<IndexEntry<'a>> {
+        let query = searched_txt.to_uppercase();
+        let query_len = query.chars().count();
+        let max_distance = fuzzy::max_distance(&query);
+        let mut found: Vec<(usize, usize)> = (0..self.count)
+            .filter_map(|i| {
+                let word = self.word(i);
+                // The distance is at least the difference of the lengths
+                let word_len = word.chars().count();
+                if word_len + max_distance < query_len || word_len > query_len + max_distance {
+                    return None;
+                }
+                let distance = fuzzy::distance(word, &query);
+                if distance <= max_distance {
+                    Some((distance, i))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        found.sort();
+        found.into_iter().take(limit).map(|(_, i)| self.entry(i)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database;
+    use std::process;
+
+    #[test]
+    fn written_index_is_opened() {
+        let dir = ::std::env::temp_dir().join(format!("rbedic-index-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let source_path = dir.join("en_bg-utf8.dat");
+        let source = "BOOK\nn книга\n^;ABC\nn азбука\n^;";
+        fs::write(&source_path, source).unwrap();
+        let mut entries = database::parse(source, Direction::EnBg);
+        entries.sort();
+        let index_path = dir.join("cache").join("en_bg-utf8.dat.idx");
+        write(&index_path, Direction::EnBg, &source_path, source.as_bytes(), Charset::Utf8, &entries).unwrap();
+        // Written again over the existing index
+        write(&index_path, Direction::EnBg, &source_path, source.as_bytes(), Charset::Utf8, &entries).unwrap();
+        // No temporary files are left
+        let files: Vec<_> = fs::read_dir(index_path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+
+        let compiled_index = CompiledIndex::open(&index_path, &source_path, Direction::EnBg, None).unwrap();
+        assert_eq!(compiled_index.len(), 2);
+        let found = compiled_index.search("book").unwrap();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].translation, "BOOK\nn книга\n");
+        assert!(CompiledIndex::open(&index_path, &source_path, Direction::BgEn, None).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,184 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Correction of words typed with the wrong keyboard layout,
+//! e.g. "vfvf" typed instead of "мама".
+
+use database::{is_cyrillic, Dictionaries};
+
+/// Cyrillic keyboard layouts
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Layout {
+    /// Bulgarian phonetic layout
+    Phonetic,
+    /// Bulgarian BDS (typewriter) layout
+    Bds,
+    /// ЙЦУКЕН layout, as found on Russian keyboards
+    Jcuken,
+}
+
+/// All supported layouts, in order of trying
+pub const LAYOUTS: [Layout; 3] = [Layout::Phonetic, Layout::Bds, Layout::Jcuken];
+
+// Pairs of (key on the US QWERTY layout, Cyrillic letter on the same key)
+const PHONETIC_KEYS: &[(char, char)] = &[
+    ('`', 'ч'), ('q', 'я'), ('w', 'в'), ('e', 'е'), ('r', 'р'), ('t', 'т'), ('y', 'ъ'),
+    ('u', 'у'), ('i', 'и'), ('o', 'о'), ('p', 'п'), ('[', 'ш'), (']', 'щ'), ('\\', 'ю'),
+    ('a', 'а'), ('s', 'с'), ('d', 'д'), ('f', 'ф'), ('g', 'г'), ('h', 'х'), ('j', 'й'),
+    ('k', 'к'), ('l', 'л'), ('z', 'з'), ('x', 'ь'), ('c', 'ц'), ('v', 'ж'), ('b', 'б'),
+    ('n', 'н'), ('m', 'м'),
+];
+
+const BDS_KEYS: &[(char, char)] = &[
+    ('w', 'у'), ('e', 'е'), ('r', 'и'), ('t', 'ш'), ('y', 'щ'), ('u', 'к'), ('i', 'с'),
+    ('o', 'д'), ('p', 'з'), ('[', 'ц'), ('a', 'ь'), ('s', 'я'), ('d', 'а'), ('f', 'о'),
+    ('g', 'ж'), ('h', 'г'), ('j', 'т'), ('k', 'н'), ('l', 'в'), (';', 'м'), ('\'', 'ч'),
+    ('z', 'ю'), ('x', 'й'), ('c', 'ъ'), ('v', 'э'), ('b', 'ф'), ('n', 'х'), ('m', 'п'),
+    (',', 'р'), ('.', 'л'), ('/', 'б'),
+];
+
+const JCUKEN_KEYS: &[(char, char)] = &[
+    ('q', 'й'), ('w', 'ц'), ('e', 'у'), ('r', 'к'), ('t', 'е'), ('y', 'н'), ('u', 'г'),
+    ('i', 'ш'), ('o', 'щ'), ('p', 'з'), ('[', 'х'), (']', 'ъ'), ('a', 'ф'), ('s', 'ы'),
+    ('d', 'в'), ('f', 'а'), ('g', 'п'), ('h', 'р'), ('j', 'о'), ('k', 'л'), ('l', 'д'),
+    (';', 'ж'), ('\'', 'э'), ('z', 'я'), ('x', 'ч'), ('c', 'с'), ('v', 'м'), ('b', 'и'),
+    ('n', 'т'), ('m', 'ь'), (',', 'б'), ('.', 'ю'),
+];
+
+impl Layout {
+    fn keys(&self) -> &'static [(char, char)] {
+        match *self {
+            Layout::Phonetic => PHONETIC_KEYS,
+            Layout::Bds => BDS_KEYS,
+            Layout::Jcuken => JCUKEN_KEYS,
+        }
+    }
+
+    /// Retype text typed on QWERTY as if this layout was active
+    pub fn to_cyrillic(&self, txt: &str) -> String {
+        let keys = self.keys();
+        txt.chars()
+            .map(|c| {
+                let lower = c.to_lowercase().next().unwrap_or(c);
+                match keys.iter().find(|&&(latin, _)| latin == lower) {
+                    Some(&(_, cyrillic)) if c != lower => {
+                        cyrillic.to_uppercase().next().unwrap_or(cyrillic)
+                    }
+                    Some(&(_, cyrillic)) => cyrillic,
+                    None => c,
+                }
+            })
+            .collect()
+    }
+
+    /// Retype text typed with this layout as if QWERTY was active
+    pub fn to_latin(&self, txt: &str) -> String {
+        let keys = self.keys();
+        txt.chars()
+            .map(|c| {
+                let lower = c.to_lowercase().next().unwrap_or(c);
+                match keys.iter().find(|&&(_, cyrillic)| cyrillic == lower) {
+                    Some(&(latin, _)) if c != lower => latin.to_ascii_uppercase(),
+                    Some(&(latin, _)) => latin,
+                    None => c,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Retype `txt` with every layout: Latin text is converted to Cyrillic and vice versa.
+/// Returns distinct variants which differ from `txt`.
+pub fn candidates(txt: &str) -> Vec<String> {
+    let to_cyrillic = !txt.chars().any(is_cyrillic);
+    let mut vec_candidates: Vec<String> = Vec::new();
+    for layout in LAYOUTS.iter() {
+        let candidate = if to_cyrillic {
+            layout.to_cyrillic(txt)
+        } else {
+            layout.to_latin(txt)
+        };
+        if candidate != txt && !vec_candidates.contains(&candidate) {
+            vec_candidates.push(candidate);
+        }
+    }
+    vec_candidates
+}
+
+/// Return the first variant of `txt` from `candidates()` which is found into `dictionaries`.
+/// Variants matching a whole word win over variants matching only the beginning of a word.
+pub fn correct(txt: &str, dictionaries: &Dictionaries) -> Option<String> {
+    let mut prefix_match: Option<String> = None;
+    for candidate in candidates(txt) {
+        match dictionaries.search(&candidate, None) {
+            Ok(_) => {
+                trace!("layout: {:?} corrected to {:?}", txt, candidate);
+                return Some(candidate);
+            }
+            Err(ref vec_result_err) if !vec_result_err.is_empty() && prefix_match.is_none() => {
+                prefix_match = Some(candidate);
+            }
+            Err(_) => (),
+        }
+    }
+    trace!("layout: {:?} corrected to {:?}", txt, prefix_match);
+    prefix_match
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::{Dictionary, Direction};
+
+    #[test]
+    fn keys_map_one_letter_each() {
+        for layout in LAYOUTS.iter() {
+            let keys = layout.keys();
+            for (i, &(latin, cyrillic)) in keys.iter().enumerate() {
+                assert!(latin.is_ascii() && !latin.is_ascii_uppercase(), "{:?} {:?}", layout, latin);
+                assert!(is_cyrillic(cyrillic) && cyrillic.is_lowercase(), "{:?} {:?}", layout, cyrillic);
+                assert!(!keys[i + 1..].iter().any(|&(other, _)| other == latin), "{:?} {:?}", layout, latin);
+                assert!(!keys[i + 1..].iter().any(|&(_, other)| other == cyrillic), "{:?} {:?}", layout, cyrillic);
+            }
+        }
+    }
+
+    #[test]
+    fn retyped_words() {
+        assert_eq!(Layout::Phonetic.to_cyrillic("mama"), "мама");
+        assert_eq!(Layout::Bds.to_cyrillic(";d;d"), "мама");
+        assert_eq!(Layout::Jcuken.to_cyrillic("vfvf"), "мама");
+        assert_eq!(Layout::Phonetic.to_cyrillic("Kniga 2"), "Книга 2");
+        assert_eq!(Layout::Jcuken.to_latin("Мама"), "Vfvf");
+        for layout in LAYOUTS.iter() {
+            assert_eq!(layout.to_latin(&layout.to_cyrillic("book")), "book");
+        }
+    }
+
+    #[test]
+    fn candidates_of_each_layout() {
+        assert_eq!(candidates("mama"), vec!["мама", "пьпь", "ьфьф"]);
+        assert_eq!(candidates("мама"), vec!["mama", ";d;d", "vfvf"]);
+        // Nothing to retype
+        assert!(candidates("123").is_empty());
+    }
+
+    #[test]
+    fn corrected_words() {
+        let dictionaries = Dictionaries {
+            en_bg: Dictionary::new(Direction::EnBg, "BOOK\nn книга\n^;"),
+            bg_en: Dictionary::new(Direction::BgEn, "МАМА\nж mother\n^;"),
+            extra: Vec::new(),
+        };
+        assert_eq!(correct("vfvf", &dictionaries), Some("мама".to_string()));
+        assert_eq!(correct("ищщл", &dictionaries), Some("book".to_string()));
+        // Only the beginning of a word
+        assert_eq!(correct("vfv", &dictionaries), Some("мам".to_string()));
+        assert_eq!(correct("zzz", &dictionaries), None);
+    }
+}
@@ -0,0 +1,67 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Bulgarian-English two-way dictionary.
+//!
+//! This library loads the bedic `.dat` files and searches into them,
+//! without any GTK dependency. The `rbedic` binary adds a GTK frontend
+//! behind the default-on `gui` feature.
+//!
+//! ```no_run
+//! use rbedic::{Dictionaries, Direction};
+//!
+//! let dictionaries = Dictionaries::new(None).expect("Unable to load dictionaries");
+//! match dictionaries.search("abandon", Some(Direction::EnBg)) {
+//!     Ok(exact) => println!("{}", exact[0].translation),
+//!     Err(prefix) => println!("{} words start with abandon", prefix.len()),
+//! }
+//! ```
+
+#[macro_use]
+extern crate lazy_static;
+#[macro_use]
+extern crate log;
+extern crate regex;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+
+extern crate dirs;
+extern crate encoding;
+extern crate flate2;
+#[cfg(unix)]
+extern crate libc;
+extern crate memmap;
+
+pub mod charset;
+pub mod database;
+pub mod dictd;
+pub mod entry;
+pub mod fulltext;
+pub mod fuzzy;
+pub mod history;
+pub mod http;
+pub mod index;
+pub mod layout;
+pub mod pattern;
+pub mod quiz;
+pub mod review;
+pub mod stardict;
+
+pub use database::{check_indexes, compile_indexes, data_dirs, find_data_dir, is_cyrillic, open_index, parse,
+                   parse_history, parse_with_progress, read_dictionary, BedicFile, DictDB, DictError,
+                   DictKey, Dictionaries, Dictionary, DictionarySource, Direction, FullTextMatch,
+                   FuzzyMatch, LoadProgress};
+pub use entry::{Entry, Sense};
+pub use history::{HistoryFile, HistoryRecord};
+pub use quiz::{Question, QuizMode};
+pub use review::{Card, Deck, Grade};
+pub use stardict::StarDict;
+pub use charset::Charset;
+pub use index::CompiledIndex;
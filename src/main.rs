@@ -8,48 +8,178 @@
 
 //#![feature(use_extern_macros)]
 extern crate env_logger;
+#[cfg(feature = "gui")]
 extern crate gdk;
+#[cfg(feature = "gui")]
+extern crate glib;
+#[cfg(feature = "gui")]
 extern crate gtk;
+#[cfg(feature = "gui")]
+extern crate regex;
 
-#[macro_use]
-extern crate lazy_static;
 #[macro_use]
 extern crate log;
-extern crate regex;
 
 extern crate clap;
 extern crate dirs;
 
-use clap::{Arg};
+extern crate rbedic;
 
+use clap::{Arg, ArgGroup, SubCommand};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+#[cfg(feature = "gui")]
 pub mod ui;
-pub mod database;
 
+#[cfg(feature = "gui")]
 use ui::App;
+use rbedic::dictd::DictServer;
+use rbedic::http::HttpServer;
+use rbedic::{stardict, Charset, Dictionaries, Dictionary, Direction, StarDict};
+
+/// Exit codes of the headless lookup mode
+const EXIT_EXACT_MATCH: i32 = 0;
+const EXIT_NO_MATCH: i32 = 1;
+const EXIT_PREFIX_MATCH: i32 = 2;
+const EXIT_LOAD_ERROR: i32 = 3;
+/// Exit code of `rbedic serve` when the server can not listen
+const EXIT_SERVE_ERROR: i32 = 4;
+/// Exit code of `rbedic` without a subcommand, when built without the `gui` feature
+#[cfg(not(feature = "gui"))]
+const EXIT_NO_GUI: i32 = 5;
+/// Exit code of errors of the command line, `EX_USAGE` of sysexits.h.
+/// Status 1 is taken by the lookup of a word which is not found.
+const EXIT_USAGE: i32 = 64;
+
+/// Default address of `rbedic serve --dict`, on the port of the DICT protocol
+const DEFAULT_DICT_ADDR: &str = "127.0.0.1:2628";
+/// Default address of `rbedic serve --http`, accepting local clients only
+const DEFAULT_HTTP_ADDR: &str = "127.0.0.1:8080";
+
+/// Number of similar words suggested by `rbedic lookup` when nothing is found
+const SIMILAR_WORDS: usize = 10;
+
+/// Default number of words shown at once
+const DEFAULT_LIMIT: &str = "100";
 
 fn main() {
     env_logger::init().unwrap();
     info!("Starting up");
 
-    const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+    const VERSION: &str = env!("CARGO_PKG_VERSION");
     let matches: clap::ArgMatches<'static> = clap::App::new("RBEdic")
                           .version(VERSION)
                           .author("Ivan Dzhonev <ivan.dzhonev@gmail.com>")
                           .about("Bulgarian-English two-way dictionary ")
+                          .after_help("Exit status: 64 on errors of the command line, 5 when started without a subcommand\nby an rbedic built without the GUI")
                           .arg(Arg::with_name("history_file")
                                .short("h")
                                .long("history")
                                .value_name("FILE")
-                               .help("Sets history file for reading.\nDefault file is ~/new_words.txt")
+                               .help("Sets history file, where the added words are recorded.\nDefault file is ~/new_words.txt")
                                .takes_value(true)
                           )
+                          .arg(Arg::with_name("echo_stdout")
+                               .long("echo-stdout")
+                               .help("Prints the added words to stdout too, a JSON record per line.\nThe output has no header line, it is not a history file")
+                               .takes_value(false)
+                          )
                           .arg(Arg::with_name("not_reading_history_file")
                                .short("n")
                                .long("noread")
                                .help("Prevents from reading the history file on startup")
                                .takes_value(false)
                           )
-                          .get_matches();
+                          .arg(Arg::with_name("data_dir")
+                               .short("d")
+                               .long("data-dir")
+                               .value_name("DIR")
+                               .help("Sets directory with dictionary files en_bg-utf8.dat and bg_en-utf8.dat.\nOverrides RBEDIC_DATA_DIR, XDG_DATA_HOME and XDG_DATA_DIRS")
+                               .takes_value(true)
+                          )
+                          .arg(Arg::with_name("encoding")
+                               .short("e")
+                               .long("encoding")
+                               .value_name("ENC")
+                               .help("Sets encoding of the dictionary files: utf-8, cp1251 or koi8-r.\nDetected by default, so the original kbedic files en_bg.dat and bg_en.dat work too")
+                               .takes_value(true)
+                               .validator(|v| v.parse::<Charset>().map(|_| ()))
+                          )
+                          .arg(Arg::with_name("stardict")
+                               .short("s")
+                               .long("stardict")
+                               .value_name("PATH")
+                               .help("Adds StarDict dictionary: an .ifo file or a directory with .ifo files.\nCan be used multiple times")
+                               .takes_value(true)
+                               .multiple(true)
+                               .number_of_values(1)
+                          )
+                          .arg(Arg::with_name("limit")
+                               .short("l")
+                               .long("limit")
+                               .value_name("N")
+                               .help("Sets number of words shown at once. More words are loaded on demand.\n0 shows all words")
+                               .takes_value(true)
+                               .default_value(DEFAULT_LIMIT)
+                               .validator(|v| v.parse::<usize>().map(|_| ()).map_err(|e| e.to_string()))
+                          )
+                          .arg(Arg::with_name("query")
+                               .short("q")
+                               .long("query")
+                               .value_name("WORD")
+                               .help("Prints translations of WORD to stdout without starting the GUI")
+                               .takes_value(true)
+                          )
+                          .subcommand(SubCommand::with_name("lookup")
+                               .about("Prints translations of WORD to stdout without starting the GUI.\nExit status: 0 exact match, 1 no match, 2 prefix match only, 3 dictionaries can not be loaded")
+                               .arg(Arg::with_name("WORD")
+                                    .help("Searched word or prefix")
+                                    .required(true)
+                                    .index(1)
+                               )
+                          )
+                          .subcommand(SubCommand::with_name("index")
+                               .about("Compiles the dictionary files into indices under $XDG_CACHE_HOME/rbedic for fast startup.\nStale indices are also rebuilt on the next start")
+                               .arg(Arg::with_name("check")
+                                    .long("check")
+                                    .help("Checks the existing indices against their checksums and the dictionary files instead")
+                               )
+                          )
+                          .subcommand(SubCommand::with_name("serve")
+                               .about("Serves the dictionaries over the network")
+                               .arg(Arg::with_name("dict")
+                                    .long("dict")
+                                    .value_name("ADDR")
+                                    .help("Speaks the DICT protocol (RFC 2229) on ADDR [default: 127.0.0.1:2628]")
+                                    .takes_value(true)
+                                    .min_values(0)
+                                    .max_values(1)
+                               )
+                               .arg(Arg::with_name("http")
+                                    .long("http")
+                                    .value_name("ADDR")
+                                    .help("Serves the JSON lookup API over HTTP on ADDR [default: 127.0.0.1:8080]:\nGET /lookup?q=WORD&dir=en-bg&limit=N and GET /entry/WORD")
+                                    .takes_value(true)
+                                    .min_values(0)
+                                    .max_values(1)
+                               )
+                               .arg(Arg::with_name("cors")
+                                    .long("cors")
+                                    .requires("http")
+                                    .help("Sends Access-Control-Allow-Origin: * with the HTTP answers, so web pages of any site can call the API")
+                               )
+                               .group(ArgGroup::with_name("protocols")
+                                    .args(&["dict", "http"])
+                                    .multiple(true)
+                                    .required(true)
+                               )
+                          )
+                          .get_matches_safe()
+                          .unwrap_or_else(|err| exit_on_usage_error(err));
 
     let mut home_dir = dirs::home_dir().unwrap();
     trace!("clap: Value of home_dir: {:?}", home_dir);
@@ -72,10 +202,316 @@ fn main() {
         prevents_reading_history_file_bool = true;
     }
 
+    // Gets a value for printing the added words to stdout if supplied by user
+    let echo_stdout: bool = matches.is_present("echo_stdout");
+    debug!("clap: Value for Echo Stdout: {:?}", echo_stdout);
+
+    // Gets a value for dictionary's directory if supplied by user
+    let data_dir: Option<String> = matches.value_of("data_dir").map(|s| s.to_string());
+    debug!("clap: Value for Data Dir: {:?}", data_dir);
+
+    // Gets a value for encoding of dictionary files if supplied by user, or detects it
+    let encoding: Option<Charset> = matches.value_of("encoding").and_then(|s| s.parse().ok());
+    debug!("clap: Value for Encoding: {:?}", encoding);
+
+    // Gets the .ifo files of StarDict dictionaries if supplied by user
+    let stardict_paths: Vec<PathBuf> = matches
+        .values_of("stardict")
+        .map(|values| values.flat_map(|path| stardict::find_ifo_files(Path::new(path))).collect())
+        .unwrap_or_default();
+    debug!("clap: Value for StarDict: {:?}", stardict_paths);
+
+    // Gets a value for number of words shown at once, 0 means no limit
+    let limit: usize = match matches.value_of("limit").unwrap_or(DEFAULT_LIMIT).parse::<usize>() {
+        Ok(0) | Err(_) => usize::MAX,
+        Ok(limit) => limit,
+    };
+    debug!("clap: Value for Limit: {:?}", limit);
+
+    // Headless lookup mode: `rbedic lookup WORD` or `rbedic -q WORD`
+    let query = match matches.subcommand_matches("lookup") {
+        Some(lookup_matches) => lookup_matches.value_of("WORD"),
+        None => matches.value_of("query"),
+    };
+    if let Some(index_matches) = matches.subcommand_matches("index") {
+        if index_matches.is_present("check") {
+            process::exit(check_indexes(data_dir.as_deref(), encoding));
+        }
+        process::exit(compile_indexes(data_dir.as_deref(), encoding));
+    }
+    if let Some(serve_matches) = matches.subcommand_matches("serve") {
+        let dict_addr = if serve_matches.is_present("dict") {
+            Some(serve_matches.value_of("dict").unwrap_or(DEFAULT_DICT_ADDR))
+        } else {
+            None
+        };
+        let http_addr = if serve_matches.is_present("http") {
+            Some(serve_matches.value_of("http").unwrap_or(DEFAULT_HTTP_ADDR))
+        } else {
+            None
+        };
+        let cors = serve_matches.is_present("cors");
+        process::exit(serve(dict_addr, http_addr, cors, data_dir.as_deref(), encoding, limit));
+    }
+    if let Some(word) = query {
+        debug!("clap: Value for lookup WORD: {:?}", word);
+        process::exit(lookup(word, data_dir.as_deref(), encoding, &stardict_paths, limit));
+    }
+
+    run_gui(&history_file_path, prevents_reading_history_file_bool, echo_stdout, data_dir, encoding, stardict_paths, limit);
+}
+
+#[cfg(feature = "gui")]
+fn run_gui(history_file_path: &str, prevents_reading_history_file_bool: bool, echo_stdout: bool, data_dir: Option<String>, encoding: Option<Charset>, stardict_paths: Vec<PathBuf>, limit: usize) {
     // Initialize the UI's initial state
-    App::new(&history_file_path, prevents_reading_history_file_bool)
+    App::new(history_file_path, prevents_reading_history_file_bool, echo_stdout, data_dir, encoding, stardict_paths, limit)
     // Connect events to the UI
     .connect_events()
     // Display the UI and execute the program
     .then_execute();
 }
+
+#[cfg(not(feature = "gui"))]
+fn run_gui(_history_file_path: &str, _prevents_reading_history_file_bool: bool, _echo_stdout: bool, _data_dir: Option<String>, _encoding: Option<Charset>, _stardict_paths: Vec<PathBuf>, _limit: usize) {
+    eprintln!("rbedic: built without the `gui` feature, use `rbedic lookup WORD`");
+    process::exit(EXIT_NO_GUI);
+}
+
+/// Compile the indices of both dictionaries and print their paths.
+/// Returns the exit code of the process.
+fn compile_indexes(data_dir: Option<&str>, encoding: Option<Charset>) -> i32 {
+    match rbedic::compile_indexes(data_dir, encoding) {
+        Ok(index_paths) => {
+            for index_path in index_paths {
+                println!("{}", index_path.display());
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("rbedic: {}\n\n{}", err, err.install_hint());
+            EXIT_LOAD_ERROR
+        }
+    }
+}
+
+/// Check the indices of both dictionaries and print their paths.
+/// Returns the exit code of the process.
+fn check_indexes(data_dir: Option<&str>, encoding: Option<Charset>) -> i32 {
+    match rbedic::check_indexes(data_dir, encoding) {
+        Ok(index_paths) => {
+            for index_path in index_paths {
+                println!("{}: ok", index_path.display());
+            }
+            0
+        }
+        Err(err) => {
+            eprintln!("rbedic: {}\nRun `rbedic index` to rebuild the indices.", err);
+            EXIT_LOAD_ERROR
+        }
+    }
+}
+
+/// Load the dictionaries and serve them with the DICT protocol on `dict_addr`
+/// and with the HTTP lookup API on `http_addr`, returning up to `limit` entries.
+/// With `cors`, web pages of any site may call the HTTP API.
+/// Returns the exit code of the process, on errors only.
+fn serve(
+    dict_addr: Option<&str>,
+    http_addr: Option<&str>,
+    cors: bool,
+    data_dir: Option<&str>,
+    encoding: Option<Charset>,
+    limit: usize,
+) -> i32 {
+    let dictionaries = match Dictionaries::load(data_dir, encoding, &mut |_| ()) {
+        Ok(dictionaries) => Arc::new(dictionaries),
+        Err(err) => {
+            eprintln!("rbedic: {}\n\n{}", err, err.install_hint());
+            return EXIT_LOAD_ERROR;
+        }
+    };
+    // Each server runs on its own thread and reports here when it fails
+    let (sender, receiver) = mpsc::channel::<(&'static str, io::Error)>();
+    if let Some(dict_addr) = dict_addr {
+        match DictServer::bind(dict_addr, dictionaries.clone(), limit) {
+            Ok(server) => {
+                if let Ok(local_addr) = server.local_addr() {
+                    eprintln!("rbedic: DICT server listening on {}", local_addr);
+                }
+                let sender = sender.clone();
+                thread::spawn(move || if let Err(err) = server.run() {
+                    let _ = sender.send(("DICT", err));
+                });
+            }
+            Err(err) => {
+                eprintln!("rbedic: DICT server on {}: {}", dict_addr, err);
+                return EXIT_SERVE_ERROR;
+            }
+        }
+    }
+    if let Some(http_addr) = http_addr {
+        match HttpServer::bind(http_addr, dictionaries.clone(), limit, cors) {
+            Ok(server) => {
+                if let Ok(local_addr) = server.local_addr() {
+                    eprintln!("rbedic: HTTP server listening on {}", local_addr);
+                }
+                let sender = sender.clone();
+                thread::spawn(move || if let Err(err) = server.run() {
+                    let _ = sender.send(("HTTP", err));
+                });
+            }
+            Err(err) => {
+                eprintln!("rbedic: HTTP server on {}: {}", http_addr, err);
+                return EXIT_SERVE_ERROR;
+            }
+        }
+    }
+    drop(sender);
+    match receiver.recv() {
+        Ok((protocol, err)) => {
+            eprintln!("rbedic: {} server: {}", protocol, err);
+            EXIT_SERVE_ERROR
+        }
+        // All servers stopped without errors
+        Err(_) => 0,
+    }
+}
+
+/// Exit code of a search result, see `DictDB::search()`
+fn match_exit_code<T>(search_result: Result<Vec<T>, Vec<T>>) -> (Vec<T>, i32) {
+    match search_result {
+        Ok(vec_result) => (vec_result, EXIT_EXACT_MATCH),
+        Err(vec_result_err) => {
+            if vec_result_err.is_empty() {
+                (vec_result_err, EXIT_NO_MATCH)
+            } else {
+                (vec_result_err, EXIT_PREFIX_MATCH)
+            }
+        }
+    }
+}
+
+/// The better of two exit codes: an exact match wins over a prefix match,
+/// which wins over no match
+fn best_exit_code(a: i32, b: i32) -> i32 {
+    if a == EXIT_EXACT_MATCH || b == EXIT_EXACT_MATCH {
+        EXIT_EXACT_MATCH
+    } else if a == EXIT_PREFIX_MATCH || b == EXIT_PREFIX_MATCH {
+        EXIT_PREFIX_MATCH
+    } else {
+        EXIT_NO_MATCH
+    }
+}
+
+/// Print `err` of clap and exit: with status 0 after the help or the version,
+/// with `EXIT_USAGE` after errors of the command line
+fn exit_on_usage_error(err: clap::Error) -> ! {
+    match err.kind {
+        clap::ErrorKind::HelpDisplayed | clap::ErrorKind::VersionDisplayed => err.exit(),
+        _ => {
+            eprintln!("{}", err.message);
+            process::exit(EXIT_USAGE);
+        }
+    }
+}
+
+/// Search for `word` and print up to `limit` matched entries to stdout.
+/// Words are looked up into the compiled index, without loading the whole dictionary,
+/// and then into the StarDict dictionaries of `stardict_paths`.
+/// Returns the exit code of the process.
+fn lookup(
+    word: &str,
+    data_dir: Option<&str>,
+    encoding: Option<Charset>,
+    stardict_paths: &[PathBuf],
+    limit: usize,
+) -> i32 {
+    let (mut translations, mut exit_code, similar) = match lookup_bedic(word, data_dir, encoding) {
+        Ok(found) => found,
+        Err(err) => {
+            eprintln!("rbedic: {}\n\n{}", err, err.install_hint());
+            return EXIT_LOAD_ERROR;
+        }
+    };
+    for ifo_path in stardict_paths {
+        let dictionary = match StarDict::open(ifo_path)
+            .and_then(|stardict| Dictionary::from_source(&stardict, &mut |_| ()))
+        {
+            Ok(dictionary) => dictionary,
+            Err(err) => {
+                eprintln!("rbedic: {}", err);
+                continue;
+            }
+        };
+        let (vec_result, stardict_exit_code) = match_exit_code(dictionary.search(word));
+        exit_code = best_exit_code(exit_code, stardict_exit_code);
+        translations.extend(vec_result.into_iter().map(|dict_db| dict_db.translation));
+    }
+    trace!("lookup: {} entries for {:?}", translations.len(), word);
+    if exit_code == EXIT_NO_MATCH && !similar.is_empty() {
+        eprintln!("rbedic: {:?} not found. Similar words: {}", word, similar.join(", "));
+    }
+    for (i, translation) in translations.iter().take(limit).enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!("{}", translation.trim_end());
+    }
+    if translations.len() > limit {
+        eprintln!("rbedic: {} more entries, use --limit to show them", translations.len() - limit);
+    }
+    exit_code
+}
+
+/// Translations of `word` into the bedic dictionary, the exit code of the lookup
+/// and the words similar to `word` when it is not found.
+/// When the compiled index can't be used, e.g. the cache directory is read-only,
+/// the dictionary file is parsed instead, with a warning.
+fn lookup_bedic(
+    word: &str,
+    data_dir: Option<&str>,
+    encoding: Option<Charset>,
+) -> Result<(Vec<String>, i32, Vec<String>), rbedic::DictError> {
+    let direction = Direction::detect(word);
+    let index_err = match rbedic::open_index(data_dir, direction, encoding) {
+        Ok(compiled_index) => {
+            let (vec_result, exit_code) = match_exit_code(compiled_index.search(word));
+            let translations = vec_result
+                .iter()
+                .map(|index_entry| index_entry.translation.to_string())
+                .collect();
+            let similar = if exit_code == EXIT_NO_MATCH {
+                compiled_index
+                    .fuzzy_search(word, SIMILAR_WORDS)
+                    .into_iter()
+                    .map(|index_entry| index_entry.word.to_string())
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            return Ok((translations, exit_code, similar));
+        }
+        Err(err) => err,
+    };
+    let dictionary = rbedic::read_dictionary(data_dir, direction, encoding)?;
+    match index_err {
+        rbedic::DictError::Io(ref index_path, ref err) => eprintln!(
+            "rbedic: warning: unable to write the index {}: {}. The dictionary file is parsed instead",
+            index_path.display(),
+            err
+        ),
+        ref err => eprintln!("rbedic: warning: {}. The dictionary file is parsed instead", err),
+    }
+    let (vec_result, exit_code) = match_exit_code(dictionary.search(word));
+    let translations = vec_result.into_iter().map(|dict_db| dict_db.translation).collect();
+    let similar = if exit_code == EXIT_NO_MATCH {
+        dictionary
+            .fuzzy_search(word, SIMILAR_WORDS)
+            .into_iter()
+            .map(|fuzzy_match| fuzzy_match.entry.word)
+            .collect()
+    } else {
+        Vec::new()
+    };
+    Ok((translations, exit_code, similar))
+}
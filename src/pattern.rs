@@ -0,0 +1,86 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Pattern queries against words: wildcards (`*tion`, `b?ok`) or regular expressions
+//! (`^.a.e$`).
+
+use regex::{self, Regex};
+
+/// Characters which make a query a regular expression rather than a wildcard pattern
+const REGEX_SYNTAX: &[char] = &['^', '$', '.', '[', ']', '(', ')', '+', '{', '}', '|', '\\'];
+
+/// Return true if `query` uses regular expression syntax
+pub fn is_regex(query: &str) -> bool {
+    query.chars().any(|c| REGEX_SYNTAX.contains(&c))
+}
+
+/// Convert a wildcard pattern into a regular expression matching whole words:
+/// `*` matches any text, `?` matches a single character.
+pub fn wildcard_to_regex(query: &str) -> String {
+    let mut regex_string = String::from("^");
+    for c in query.chars() {
+        match c {
+            '*' => regex_string.push_str(".*"),
+            '?' => regex_string.push('.'),
+            _ => regex_string.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    regex_string.push('$');
+    regex_string
+}
+
+/// Compile `query` for case insensitive matching against words.
+/// Queries with regular expression syntax are used as they are,
+/// the others are wildcard patterns, see `wildcard_to_regex()`.
+pub fn compile(query: &str) -> Result<Regex, regex::Error> {
+    let regex_string = if is_regex(query) {
+        query.to_string()
+    } else {
+        wildcard_to_regex(query)
+    };
+    trace!("pattern: {:?} -> {:?}", query, regex_string);
+    Regex::new(&format!("(?i){}", regex_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn regex_syntax() {
+        assert!(is_regex("^.a.e$"));
+        assert!(is_regex("bo+k"));
+        assert!(!is_regex("*tion"));
+        assert!(!is_regex("b?ok"));
+        assert!(!is_regex("well-being"));
+    }
+
+    #[test]
+    fn wildcards() {
+        assert_eq!(wildcard_to_regex("*tion"), "^.*tion$");
+        assert_eq!(wildcard_to_regex("b?ok"), "^b.ok$");
+        // Everything else is matched literally
+        assert_eq!(wildcard_to_regex("well-being"), r"^well\-being$");
+        assert_eq!(wildcard_to_regex("a&b*"), r"^a\&b.*$");
+    }
+
+    #[test]
+    fn compiled_queries() {
+        let wildcard = compile("b?ok*").unwrap();
+        assert!(wildcard.is_match("BOOKCASE"));
+        assert!(wildcard.is_match("book"));
+        assert!(!wildcard.is_match("notebook"));
+        let hyphen = compile("well-*").unwrap();
+        assert!(hyphen.is_match("WELL-BEING"));
+        assert!(!hyphen.is_match("WELLBEING"));
+        let regex = compile("^.a.e$").unwrap();
+        assert!(regex.is_match("CAKE"));
+        assert!(!regex.is_match("CAKES"));
+        assert!(compile("(unclosed").is_err());
+    }
+}
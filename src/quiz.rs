@@ -0,0 +1,414 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Quizzes of the history words, besides the flashcards of `review`.
+//!
+//! A multiple-choice question shows a headword with its translation among the
+//! translations of other entries of the dictionary. These wrong choices come from
+//! headwords sharing a prefix with the asked one or of a similar length, so that
+//! they are not ruled out at a glance.
+//!
+//! A typed-answer question asks for the translation in a given direction: the
+//! English to Bulgarian questions of a Bulgarian headword show its English glosses
+//! and expect the headword. Answers are compared by `normalize_answer()`.
+//!
+//! Entries which are not split into senses are asked with the parts of their raw
+//! text, see `Entry::glosses()`.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use database::{DictDB, Dictionaries, Dictionary, Direction};
+use entry::gloss_word;
+
+/// Number of choices of a multiple-choice question
+pub const CHOICES: usize = 4;
+/// Glosses shown as the translation of a headword
+const SHOWN_GLOSSES: usize = 2;
+/// Entries taken on each side of the asked headword into the sorted dictionary,
+/// they share a prefix with it
+const NEIGHBORS: usize = 40;
+/// Largest difference in characters between the lengths of the asked headword and
+/// of the headwords of the wrong choices
+const LENGTH_TOLERANCE: usize = 2;
+/// Random entries tried when the neighbors do not give enough wrong choices
+const RANDOM_TRIES: usize = 500;
+
+/// How the questions are answered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuizMode {
+    /// Choose the translation of a headword
+    MultipleChoice,
+    /// Type the translation, from the language of the words of the direction,
+    /// e.g. `Typed(Direction::EnBg)` shows English words and expects Bulgarian answers
+    Typed(Direction),
+}
+
+impl QuizMode {
+    /// Name shown to the user
+    pub fn label(&self) -> &'static str {
+        match *self {
+            QuizMode::MultipleChoice => "Multiple choice",
+            QuizMode::Typed(Direction::EnBg) => "Typed answer EN\u{2192}BG",
+            QuizMode::Typed(Direction::BgEn) => "Typed answer BG\u{2192}EN",
+        }
+    }
+}
+
+/// All quiz modes
+pub const QUIZ_MODES: [QuizMode; 3] = [
+    QuizMode::MultipleChoice,
+    QuizMode::Typed(Direction::EnBg),
+    QuizMode::Typed(Direction::BgEn),
+];
+
+/// A question about a history word
+#[derive(Clone, Debug, PartialEq)]
+pub struct Question {
+    /// The asked history word
+    pub word: DictDB,
+    /// Text of the question
+    pub prompt: String,
+    /// Translations to choose from, empty for the typed answers
+    pub choices: Vec<String>,
+    /// Index of the right translation into `choices`
+    pub right_choice: usize,
+    /// Right typed answers, the first one is shown after a wrong answer
+    pub answers: Vec<String>,
+}
+
+impl Question {
+    /// A multiple-choice question about `word`, with wrong choices from its
+    /// dictionary into `dictionaries`. Returns `None` if the entry of `word`
+    /// has no translation or the dictionary has too few entries.
+    pub fn multiple_choice(word: &DictDB, dictionaries: &Dictionaries, rng: &mut Rng) -> Option<Question> {
+        let glosses = glosses(word);
+        if glosses.is_empty() {
+            return None;
+        }
+        let right = shown_glosses(&glosses);
+        let mut choices = wrong_choices(word, &right, dictionaries.get(word.direction), rng);
+        if choices.len() < CHOICES - 1 {
+            debug!("multiple_choice: Not enough choices for {:?}", word.word);
+            return None;
+        }
+        let right_choice = rng.below(CHOICES);
+        choices.insert(right_choice, right.clone());
+        Some(Question {
+            word: word.clone(),
+            prompt: word.word.clone(),
+            choices,
+            right_choice,
+            answers: vec![right],
+        })
+    }
+
+    /// A typed-answer question about `word`, asked from the language of the words
+    /// of `direction`. Returns `None` if the entry of `word` has no translation.
+    pub fn typed(word: &DictDB, direction: Direction) -> Option<Question> {
+        let glosses = glosses(word);
+        if glosses.is_empty() {
+            return None;
+        }
+        let (prompt, answers) = if word.direction == direction {
+            let answers = glosses
+                .iter()
+                .map(|gloss| gloss[gloss_word(gloss)].to_string())
+                .filter(|answer| !answer.is_empty())
+                .collect();
+            (word.word.clone(), answers)
+        } else {
+            (shown_glosses(&glosses), vec![word.word.clone()])
+        };
+        Some(Question {
+            word: word.clone(),
+            prompt,
+            choices: Vec::new(),
+            right_choice: 0,
+            answers,
+        })
+    }
+
+    /// Return true if the typed `answer` is one of `answers`, or a whole gloss
+    /// with its qualifiers. See `normalize_answer()`.
+    pub fn is_right_answer(&self, answer: &str) -> bool {
+        let answer = normalize_answer(answer);
+        !answer.is_empty()
+            && (self.answers.iter().any(|right| normalize_answer(right) == answer)
+                || glosses(&self.word).iter().any(|gloss| normalize_answer(gloss) == answer))
+    }
+
+    /// The right answer, shown after a wrong one
+    pub fn right_answer(&self) -> &str {
+        match self.choices.get(self.right_choice) {
+            Some(choice) => choice,
+            None => self.answers.first().map_or("", |answer| answer.as_str()),
+        }
+    }
+}
+
+/// Questions about `words` in a random order, at most `count` of them.
+/// Words which can not be asked are left out.
+pub fn questions(words: &[DictDB], mode: QuizMode, dictionaries: &Dictionaries, count: usize) -> Vec<Question> {
+    let mut rng = Rng::new();
+    let mut words = words.to_vec();
+    rng.shuffle(&mut words);
+    words
+        .iter()
+        .filter_map(|word| match mode {
+            QuizMode::MultipleChoice => Question::multiple_choice(word, dictionaries, &mut rng),
+            QuizMode::Typed(direction) => Question::typed(word, direction),
+        })
+        .take(count)
+        .collect()
+}
+
+/// Lowercase `text` without stress marks, with single spaces and without the
+/// punctuation at the ends, but for the parentheses of the qualifiers, to compare
+/// answers: `Изоставя̀м.` -> `изоставям`
+pub fn normalize_answer(text: &str) -> String {
+    let folded: String = text
+        .chars()
+        .flat_map(char::to_lowercase)
+        .filter_map(|c| match c {
+            // Combining diacritical marks, used for the stress
+            c if ('\u{300}'..='\u{36f}').contains(&c) => None,
+            // Apostrophes and accents written as stress marks
+            '\'' | '`' | '\u{b4}' | '\u{2019}' => None,
+            // Stressed vowels of the Cyrillic and of the Latin alphabet
+            'ѝ' => Some('и'),
+            'ѐ' => Some('е'),
+            'á' | 'à' => Some('a'),
+            'é' | 'è' => Some('e'),
+            'í' | 'ì' => Some('i'),
+            'ó' | 'ò' => Some('o'),
+            'ú' | 'ù' => Some('u'),
+            'ý' => Some('y'),
+            c => Some(c),
+        })
+        .collect();
+    folded
+        .split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_ascii_punctuation() && c != '(' && c != ')')
+        .trim()
+        .to_string()
+}
+
+/// Glosses of the entry of `word`, see `Entry::glosses()`
+fn glosses(word: &DictDB) -> Vec<String> {
+    word.entry().glosses()
+}
+
+/// Translation shown for the glosses of a headword
+fn shown_glosses(glosses: &[String]) -> String {
+    glosses
+        .iter()
+        .take(SHOWN_GLOSSES)
+        .map(|gloss| gloss.as_str())
+        .collect::<Vec<&str>>()
+        .join(", ")
+}
+
+/// Up to `CHOICES - 1` translations of other entries of `dictionary`, different
+/// from the `right` one. The neighbors of `word` into the sorted entries are tried
+/// first, then random entries, keeping the headwords of a similar length.
+fn wrong_choices(word: &DictDB, right: &str, dictionary: &Dictionary, rng: &mut Rng) -> Vec<String> {
+    if dictionary.is_empty() {
+        return Vec::new();
+    }
+    let length = word.word.chars().count();
+    let similar = |index: usize| {
+        let entry_word = dictionary.word(index);
+        let entry_length = entry_word.chars().count();
+        entry_word != word.word && entry_length + LENGTH_TOLERANCE >= length && entry_length <= length + LENGTH_TOLERANCE
+    };
+    let position = dictionary.prefix_range(&word.word).start;
+    let start = position.saturating_sub(NEIGHBORS);
+    let end = (position + NEIGHBORS).min(dictionary.len());
+    let mut candidates: Vec<usize> = (start..end).filter(|&index| similar(index)).collect();
+    rng.shuffle(&mut candidates);
+    for _ in 0..RANDOM_TRIES {
+        let index = rng.below(dictionary.len());
+        if similar(index) {
+            candidates.push(index);
+        }
+    }
+
+    let mut seen = vec![normalize_answer(right)];
+    let mut choices: Vec<String> = Vec::new();
+    for index in candidates {
+        if choices.len() == CHOICES - 1 {
+            break;
+        }
+        let glosses = glosses(&dictionary.entry(index));
+        if glosses.is_empty() {
+            continue;
+        }
+        let choice = shown_glosses(&glosses);
+        let normalized = normalize_answer(&choice);
+        if !seen.contains(&normalized) {
+            seen.push(normalized);
+            choices.push(choice);
+        }
+    }
+    choices
+}
+
+/// Xorshift generator of the random questions and choices.
+/// Quizzes need no better randomness than this.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    /// A generator seeded by the current time
+    pub fn new() -> Rng {
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs() ^ (u64::from(d.subsec_nanos()) << 32))
+            .unwrap_or(0);
+        Rng::with_seed(seed)
+    }
+
+    pub fn with_seed(seed: u64) -> Rng {
+        // The state of xorshift must not be zero
+        Rng {
+            state: seed | 1,
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// A number from 0 to `n - 1`, `n` must not be zero
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    /// Put `items` into a random order
+    pub fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = self.below(i + 1);
+            items.swap(i, j);
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Rng {
+        Rng::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EN_BG: &str = "ABANDONMENT
+n изоставяне
+^;ABC
+n азбука
+основни понятия
+^;BOOK
+n книга
+^;COOK
+v готвя
+^;HOOK
+n кука
+^;LOOK
+v гледам
+^;NOOK
+n ъгъл
+^;ROOK
+n топ
+^;TOME
+n книга
+^;VOLUME
+n (на книга) том
+^;";
+
+    fn dictionaries() -> Dictionaries {
+        Dictionaries {
+            en_bg: Dictionary::new(Direction::EnBg, EN_BG),
+            bg_en: Dictionary::new(Direction::BgEn, ""),
+            extra: Vec::new(),
+        }
+    }
+
+    fn word(dictionaries: &Dictionaries, headword: &str) -> DictDB {
+        let dictionary = &dictionaries.en_bg;
+        let index = dictionary.prefix_range(headword).start;
+        dictionary.entry(index)
+    }
+
+    #[test]
+    fn normalized_answers() {
+        assert_eq!(normalize_answer("Изоставя̀м."), "изоставям");
+        assert_eq!(normalize_answer("  нѐщо   и   нѝщо! "), "нещо и нищо");
+        assert_eq!(normalize_answer("Abandón"), "abandon");
+        assert_eq!(normalize_answer("ab'andon"), "abandon");
+        assert_eq!(normalize_answer("(на книга) том;"), "(на книга) том");
+        assert_eq!(normalize_answer("том (на книга)"), "том (на книга)");
+        assert_eq!(normalize_answer("..."), "");
+    }
+
+    #[test]
+    fn distractors() {
+        let dictionaries = dictionaries();
+        let book = word(&dictionaries, "BOOK");
+        for seed in 0..20 {
+            let question = Question::multiple_choice(&book, &dictionaries, &mut Rng::with_seed(seed)).unwrap();
+            assert_eq!(question.choices.len(), CHOICES);
+            assert_eq!(question.right_answer(), "книга");
+            let mut normalized: Vec<String> = question.choices.iter().map(|c| normalize_answer(c)).collect();
+            normalized.sort();
+            normalized.dedup();
+            assert_eq!(normalized.len(), CHOICES, "{:?}", question.choices);
+            // TOME has the same translation, ABANDONMENT is too long
+            assert!(!question.choices.contains(&"изоставяне".to_string()));
+            assert_eq!(question, Question::multiple_choice(&book, &dictionaries, &mut Rng::with_seed(seed)).unwrap());
+        }
+    }
+
+    #[test]
+    fn typed_answers() {
+        let dictionaries = dictionaries();
+        let question = Question::typed(&word(&dictionaries, "VOLUME"), Direction::EnBg).unwrap();
+        assert_eq!(question.prompt, "VOLUME");
+        assert_eq!(question.answers, vec!["том".to_string()]);
+        assert!(question.is_right_answer("То̀м"));
+        assert!(question.is_right_answer("(на книга) том"));
+        assert!(!question.is_right_answer("книга"));
+        assert!(!question.is_right_answer(" "));
+
+        let question = Question::typed(&word(&dictionaries, "BOOK"), Direction::BgEn).unwrap();
+        assert_eq!(question.prompt, "книга");
+        assert!(question.is_right_answer("book"));
+        assert!(!question.is_right_answer("tome"));
+    }
+
+    #[test]
+    fn raw_entries_are_asked() {
+        let dictionaries = dictionaries();
+        let abc = word(&dictionaries, "ABC");
+        assert!(!abc.entry().is_structured());
+        let question = Question::multiple_choice(&abc, &dictionaries, &mut Rng::with_seed(7)).unwrap();
+        assert_eq!(question.right_answer(), "азбука, основни понятия");
+        let question = Question::typed(&abc, Direction::EnBg).unwrap();
+        assert!(question.is_right_answer("основни понятия"));
+        let question = Question::typed(&abc, Direction::BgEn).unwrap();
+        assert_eq!(question.prompt, "азбука, основни понятия");
+        assert!(question.is_right_answer("abc"));
+    }
+}
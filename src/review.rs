@@ -0,0 +1,409 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Spaced repetition of the history words, after the SM-2 algorithm of SuperMemo.
+//!
+//! Each word has a `Card` with an ease factor and an interval in days. Answers which
+//! are remembered make the interval grow by the ease factor, forgotten ones start the
+//! word over. The cards are kept into a sidecar file of the history file, e.g.
+//! `~/new_words.txt.review`, in the JSON Lines format of the history file. Like the
+//! history file, the sidecar file is locked while it is read or written and replaced
+//! by renaming a complete copy, so several instances of rbedic may share it.
+//!
+//! Days are counted in UTC, from 1970-01-01.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::prelude::*;
+use std::io::{self, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json;
+
+use database::{DictDB, Direction};
+use history::{open_locked, replace_file};
+
+/// Value of `format` into the header line
+const FORMAT_NAME: &str = "rbedic-review";
+const FORMAT_VERSION: u32 = 1;
+
+/// Ease factor of new cards, and the lowest one
+const INITIAL_EASE: f64 = 2.5;
+const MIN_EASE: f64 = 1.3;
+/// Interval growth of the Hard answers, and the extra growth of the Easy ones
+const HARD_FACTOR: f64 = 1.2;
+const EASY_BONUS: f64 = 1.3;
+
+/// How well a word was remembered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Grade {
+    /// Forgotten, the word is learnt again
+    Again,
+    /// Remembered with difficulty
+    Hard,
+    Good,
+    /// Remembered at once
+    Easy,
+}
+
+/// All grades, from the worst
+pub const GRADES: [Grade; 4] = [Grade::Again, Grade::Hard, Grade::Good, Grade::Easy];
+
+impl Grade {
+    /// Name shown to the user
+    pub fn label(&self) -> &'static str {
+        match *self {
+            Grade::Again => "Again",
+            Grade::Hard => "Hard",
+            Grade::Good => "Good",
+            Grade::Easy => "Easy",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Header {
+    format: String,
+    version: u32,
+}
+
+/// Scheduling state of a history word
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Card {
+    pub headword: String,
+    #[serde(with = "::history::direction_name")]
+    pub direction: Direction,
+    pub ease: f64,
+    /// Days from the last review to the next one
+    pub interval: u64,
+    /// Answers remembered in a row
+    pub repetitions: u32,
+    /// Number of times the word was forgotten
+    #[serde(default)]
+    pub lapses: u32,
+    /// Day of the next review
+    pub due: u64,
+    /// Answers of the quizzes, see `quiz`
+    #[serde(default)]
+    pub quiz_right: u32,
+    #[serde(default)]
+    pub quiz_wrong: u32,
+}
+
+impl Card {
+    /// A card of a word never reviewed, due `today`
+    pub fn new(headword: &str, direction: Direction, today: u64) -> Card {
+        Card {
+            headword: headword.to_string(),
+            direction,
+            ease: INITIAL_EASE,
+            interval: 0,
+            repetitions: 0,
+            lapses: 0,
+            due: today,
+            quiz_right: 0,
+            quiz_wrong: 0,
+        }
+    }
+
+    pub fn is_due(&self, today: u64) -> bool {
+        self.due <= today
+    }
+
+    /// Schedule the next review after answering with `grade` on `today`
+    pub fn answer(&mut self, grade: Grade, today: u64) {
+        let interval = self.interval as f64;
+        // Interval of a Good answer, growing by at least a day
+        let good_interval = match self.repetitions {
+            0 => 1.0,
+            1 => 6.0,
+            _ => (interval * self.ease).max(interval + 1.0),
+        };
+        match grade {
+            Grade::Again => {
+                self.repetitions = 0;
+                self.lapses += 1;
+                self.interval = 0;
+                self.ease = (self.ease - 0.2).max(MIN_EASE);
+            }
+            Grade::Hard => {
+                self.interval = if self.repetitions == 0 {
+                    1
+                } else {
+                    (interval * HARD_FACTOR).max(interval + 1.0).round() as u64
+                };
+                self.repetitions += 1;
+                self.ease = (self.ease - 0.15).max(MIN_EASE);
+            }
+            Grade::Good => {
+                self.interval = good_interval.round() as u64;
+                self.repetitions += 1;
+            }
+            Grade::Easy => {
+                self.interval = (good_interval * EASY_BONUS).round().max(4.0) as u64;
+                self.repetitions += 1;
+                self.ease += 0.15;
+            }
+        }
+        self.due = today + self.interval;
+    }
+}
+
+/// The cards of the history words, with their sidecar file
+#[derive(Clone, Debug)]
+pub struct Deck {
+    path: PathBuf,
+    cards: Vec<Card>,
+}
+
+impl Deck {
+    /// Path of the sidecar file of the history file at `history_path`
+    pub fn path_for(history_path: &Path) -> PathBuf {
+        let mut name = history_path.to_path_buf().into_os_string();
+        name.push(".review");
+        PathBuf::from(name)
+    }
+
+    /// Read the cards of the history file at `history_path`. A missing sidecar file
+    /// has no cards, all words are new then.
+    pub fn open(history_path: &Path) -> io::Result<Deck> {
+        let path = Deck::path_for(history_path);
+        let cards = match open_locked(&path, false)? {
+            Some(mut file) => read_cards(&path, &mut file)?,
+            None => Vec::new(),
+        };
+        Ok(Deck { path, cards })
+    }
+
+    /// Remove the cards of the words which are no longer in `history`, e.g. deleted
+    /// by another instance of rbedic, so that a word added again starts as new
+    pub fn prune(&mut self, history: &[DictDB]) -> io::Result<()> {
+        let words: HashSet<(&str, Direction)> = history
+            .iter()
+            .map(|dict_db| (dict_db.word.as_str(), dict_db.direction))
+            .collect();
+        let is_in_history = |card: &Card| words.contains(&(card.headword.as_str(), card.direction));
+        if self.cards.iter().all(is_in_history) {
+            return Ok(());
+        }
+        self.update(|cards| {
+            let len = cards.len();
+            cards.retain(is_in_history);
+            debug!("Review file: {} cards of deleted words removed", len - cards.len());
+        })
+    }
+
+    pub fn card(&self, headword: &str, direction: Direction) -> Option<&Card> {
+        self.cards
+            .iter()
+            .find(|card| card.headword == headword && card.direction == direction)
+    }
+
+    /// The words of `history` due `today`, in the order of `history`.
+    /// Words without a card are new, they are due at once.
+    pub fn due(&self, history: &[DictDB], today: u64) -> Vec<DictDB> {
+        history
+            .iter()
+            .filter(|dict_db| {
+                self.card(&dict_db.word, dict_db.direction)
+                    .is_none_or(|card| card.is_due(today))
+            })
+            .cloned()
+            .collect()
+    }
+
+    pub fn due_count(&self, history: &[DictDB], today: u64) -> usize {
+        self.due(history, today).len()
+    }
+
+    /// Record the answer `grade` for `word` and write the sidecar file
+    pub fn answer(&mut self, word: &DictDB, grade: Grade, today: u64) -> io::Result<()> {
+        self.update(|cards| {
+            let card = card_mut(cards, word, today);
+            card.answer(grade, today);
+            debug!("Review of {:?}: {:?} -> {:?}", word.word, grade, card);
+        })
+    }
+
+    /// Count a quiz answer for `word` and write the sidecar file.
+    /// The schedule of the reviews does not change.
+    pub fn record_quiz(&mut self, word: &DictDB, right: bool, today: u64) -> io::Result<()> {
+        self.update(|cards| {
+            let card = card_mut(cards, word, today);
+            if right {
+                card.quiz_right += 1;
+            } else {
+                card.quiz_wrong += 1;
+            }
+            debug!("Quiz of {:?}: {} -> {:?}", word.word, right, card);
+        })
+    }
+
+    /// Read the cards of the sidecar file, change them with `change` and write them
+    /// back, all under the lock. The deck gets the cards written by other instances too.
+    fn update<F: FnOnce(&mut Vec<Card>)>(&mut self, change: F) -> io::Result<()> {
+        // Held until the file is replaced
+        let file = open_locked(&self.path, true)?;
+        let mut cards = match file {
+            Some(ref file) => read_cards(&self.path, &mut file.try_clone()?)?,
+            None => Vec::new(),
+        };
+        change(&mut cards);
+        let header = Header {
+            format: FORMAT_NAME.to_string(),
+            version: FORMAT_VERSION,
+        };
+        let mut text = serde_json::to_string(&header).unwrap_or_default();
+        text.push('\n');
+        for card in &cards {
+            // Serializing strings and numbers can not fail
+            text.push_str(&serde_json::to_string(card).unwrap_or_default());
+            text.push('\n');
+        }
+        replace_file(&self.path, &text)?;
+        self.cards = cards;
+        Ok(())
+    }
+}
+
+/// Read the cards of the locked sidecar `file` at `path`. An empty file has no cards.
+fn read_cards(path: &Path, file: &mut File) -> io::Result<Vec<Card>> {
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+    let mut lines = content.lines().filter(|line| !line.trim().is_empty());
+    if let Some(first_line) = lines.next() {
+        let header: Header = serde_json::from_str(first_line)
+            .map_err(|err| io::Error::new(ErrorKind::InvalidData, err))?;
+        if header.format != FORMAT_NAME || header.version > FORMAT_VERSION {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} is not a review file of this rbedic", path.display()),
+            ));
+        }
+    }
+    let mut cards: Vec<Card> = Vec::new();
+    for line in lines {
+        match serde_json::from_str(line) {
+            Ok(card) => cards.push(card),
+            Err(err) => warn!("Review file {:?}: {}", path, err),
+        }
+    }
+    Ok(cards)
+}
+
+/// The card of `word` into `cards`, a new one due `today` if it has none
+fn card_mut<'a>(cards: &'a mut Vec<Card>, word: &DictDB, today: u64) -> &'a mut Card {
+    let position = cards
+        .iter()
+        .position(|card| card.headword == word.word && card.direction == word.direction);
+    let index = match position {
+        Some(index) => index,
+        None => {
+            cards.push(Card::new(&word.word, word.direction, today));
+            cards.len() - 1
+        }
+    };
+    &mut cards[index]
+}
+
+/// Number of the current day, see `Card::due`
+pub fn today() -> u64 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    seconds / 86_400
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::process;
+    use std::slice;
+
+    fn word(headword: &str, direction: Direction) -> DictDB {
+        DictDB {
+            word: headword.to_string(),
+            translation: format!("{}\nn ...", headword),
+            direction,
+        }
+    }
+
+    #[test]
+    fn good_answers_grow_the_interval() {
+        let mut card = Card::new("BOOK", Direction::EnBg, 100);
+        let intervals: Vec<u64> = (0..4)
+            .map(|_| {
+                card.answer(Grade::Good, 100);
+                card.interval
+            })
+            .collect();
+        // 1 day, 6 days, then times the ease factor of 2.5
+        assert_eq!(intervals, vec![1, 6, 15, 38]);
+        assert_eq!(card.due, 138);
+        assert_eq!(card.repetitions, 4);
+        assert_eq!(card.ease, INITIAL_EASE);
+    }
+
+    #[test]
+    fn hard_easy_and_again() {
+        let mut card = Card::new("BOOK", Direction::EnBg, 0);
+        card.answer(Grade::Easy, 0);
+        assert_eq!(card.interval, 4);
+        assert!((card.ease - 2.65).abs() < 1e-9);
+        card.answer(Grade::Hard, 4);
+        // 4 * 1.2 = 4.8, rounded
+        assert_eq!(card.interval, 5);
+        assert_eq!(card.due, 9);
+        assert!((card.ease - 2.5).abs() < 1e-9);
+        card.answer(Grade::Again, 9);
+        assert_eq!((card.interval, card.repetitions, card.lapses, card.due), (0, 0, 1, 9));
+        assert!((card.ease - 2.3).abs() < 1e-9);
+        assert!(card.is_due(9));
+    }
+
+    #[test]
+    fn ease_floor() {
+        let mut card = Card::new("BOOK", Direction::EnBg, 0);
+        for _ in 0..10 {
+            card.answer(Grade::Again, 0);
+        }
+        assert_eq!(card.ease, MIN_EASE);
+        card.answer(Grade::Hard, 0);
+        assert_eq!(card.ease, MIN_EASE);
+        assert_eq!(card.lapses, 10);
+    }
+
+    #[test]
+    fn deck_is_shared_and_pruned() {
+        let dir = ::std::env::temp_dir().join(format!("rbedic-review-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let history_path = dir.join("new_words.txt");
+        let book = word("BOOK", Direction::EnBg);
+        let kniga = word("КНИГА", Direction::BgEn);
+
+        let mut deck = Deck::open(&history_path).unwrap();
+        let mut other_deck = Deck::open(&history_path).unwrap();
+        deck.answer(&book, Grade::Good, 10).unwrap();
+        // The answers of another instance are kept
+        other_deck.answer(&kniga, Grade::Again, 10).unwrap();
+        assert!(other_deck.card("BOOK", Direction::EnBg).is_some());
+        assert_eq!(Deck::open(&history_path).unwrap().cards.len(), 2);
+
+        // BOOK was deleted from the history
+        deck.prune(slice::from_ref(&kniga)).unwrap();
+        let reopened = Deck::open(&history_path).unwrap();
+        assert!(reopened.card("BOOK", Direction::EnBg).is_none());
+        assert_eq!(reopened.card("КНИГА", Direction::BgEn).map(|card| card.lapses), Some(1));
+        assert_eq!(reopened.due(&[book, kniga], 10).len(), 2);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,429 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reader of StarDict dictionaries: the `.ifo` file with the description,
+//! the `.idx` (or `.idx.gz`) index and the `.dict` (or dictzip `.dict.dz`) data.
+
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use flate2::read::GzDecoder;
+
+use database::{io_error, DictDB, DictError, DictionarySource, Direction};
+
+const IFO_MAGIC: &str = "StarDict's dict ifo file";
+
+/// A StarDict dictionary, described by its `.ifo` file
+#[derive(Clone, Debug)]
+pub struct StarDict {
+    ifo_path: PathBuf,
+    /// Name of the dictionary
+    pub bookname: String,
+    /// Number of words, as declared into the `.ifo` file
+    pub wordcount: usize,
+    /// Size of the data offsets into the `.idx` file: 32 or 64 bits
+    idx_offset_bits: u32,
+    /// Types of the data fields, if all entries have the same ones
+    same_type_sequence: Option<String>,
+}
+
+impl StarDict {
+    /// Read the `.ifo` file of a dictionary
+    pub fn open(ifo_path: &Path) -> Result<StarDict, DictError> {
+        let data = read_file(ifo_path)?;
+        let text = String::from_utf8_lossy(&data);
+        let mut lines = text.lines();
+        if lines.next().map(|line| line.trim()) != Some(IFO_MAGIC) {
+            return Err(format_error(ifo_path, "not a StarDict .ifo file"));
+        }
+        let mut stardict = StarDict {
+            ifo_path: ifo_path.to_path_buf(),
+            bookname: String::new(),
+            wordcount: 0,
+            idx_offset_bits: 32,
+            same_type_sequence: None,
+        };
+        for line in lines {
+            let mut key_value = line.splitn(2, '=');
+            let key = key_value.next().unwrap_or("").trim();
+            let value = key_value.next().unwrap_or("").trim();
+            match key {
+                "bookname" => stardict.bookname = value.to_string(),
+                "wordcount" => stardict.wordcount = value.parse().unwrap_or(0),
+                "idxoffsetbits" => stardict.idx_offset_bits = value.parse().unwrap_or(32),
+                "sametypesequence" if !value.is_empty() => {
+                    stardict.same_type_sequence = Some(value.to_string())
+                }
+                _ => (),
+            }
+        }
+        if stardict.idx_offset_bits != 32 && stardict.idx_offset_bits != 64 {
+            return Err(format_error(ifo_path, "idxoffsetbits is neither 32 nor 64"));
+        }
+        if stardict.bookname.is_empty() {
+            stardict.bookname = ifo_path
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().into_owned())
+                .unwrap_or_default();
+        }
+        debug!("StarDict {:?}: {:?}", ifo_path, stardict);
+        Ok(stardict)
+    }
+
+    /// The first existing file next to the `.ifo` file, with one of `extensions`
+    fn companion(&self, extensions: &[&str]) -> Result<PathBuf, DictError> {
+        let candidates: Vec<PathBuf> = extensions
+            .iter()
+            .map(|extension| self.ifo_path.with_extension(extension))
+            .collect();
+        candidates
+            .iter()
+            .find(|path| path.is_file())
+            .cloned()
+            .ok_or(DictError::NotFound(candidates))
+    }
+
+    /// Headwords with the offset and the size of their data into the `.dict` file
+    fn read_idx(&self) -> Result<Vec<(String, usize, usize)>, DictError> {
+        let idx_path = self.companion(&["idx", "idx.gz"])?;
+        let data = read_file(&idx_path)?;
+        let offset_len = (self.idx_offset_bits / 8) as usize;
+        let mut words: Vec<(String, usize, usize)> = Vec::with_capacity(self.wordcount);
+        let mut position = 0;
+        while position < data.len() {
+            let word_end = match data[position..].iter().position(|&byte| byte == 0) {
+                Some(word_len) => position + word_len,
+                None => return Err(format_error(&idx_path, "unterminated word")),
+            };
+            let fields_end = word_end + 1 + offset_len + 4;
+            if fields_end > data.len() {
+                return Err(format_error(&idx_path, "truncated entry"));
+            }
+            let word = String::from_utf8_lossy(&data[position..word_end]).into_owned();
+            let offset = read_be(&data[word_end + 1..word_end + 1 + offset_len]);
+            let size = read_be(&data[word_end + 1 + offset_len..fields_end]);
+            words.push((word, offset, size));
+            position = fields_end;
+        }
+        Ok(words)
+    }
+
+    /// Text of the data fields of one entry
+    fn entry_text(&self, data: &[u8]) -> String {
+        let mut fields: Vec<String> = Vec::new();
+        let mut position = 0;
+        match self.same_type_sequence {
+            Some(ref types) => {
+                let types: Vec<char> = types.chars().collect();
+                for (i, &field_type) in types.iter().enumerate() {
+                    // The last field has no terminator nor size
+                    let last = i + 1 == types.len();
+                    let (field, next) = read_field(data, position, field_type, last);
+                    fields.extend(field);
+                    position = next;
+                }
+            }
+            None => while position < data.len() {
+                let field_type = data[position] as char;
+                let (field, next) = read_field(data, position + 1, field_type, false);
+                fields.extend(field);
+                position = next;
+            },
+        }
+        fields.join("\n")
+    }
+}
+
+impl DictionarySource for StarDict {
+    fn name(&self) -> String {
+        self.bookname.clone()
+    }
+
+    /// Headwords are uppercased, like the bedic ones, and the translation starts
+    /// with the headword line. The direction of an entry is detected by the script
+    /// of its headword.
+    fn read(&self, progress: &mut dyn FnMut(usize)) -> Result<Vec<DictDB>, DictError> {
+        let words = self.read_idx()?;
+        let dict_path = self.companion(&["dict", "dict.dz"])?;
+        let data = read_file(&dict_path)?;
+        let mut entries: Vec<DictDB> = Vec::with_capacity(words.len());
+        for (word, offset, size) in words {
+            let entry_data = match offset.checked_add(size).and_then(|end| data.get(offset..end)) {
+                Some(entry_data) => entry_data,
+                None => return Err(format_error(&dict_path, "entry out of the data")),
+            };
+            let text = self.entry_text(entry_data);
+            entries.push(DictDB {
+                word: word.to_uppercase(),
+                translation: format!("{}\n{}\n", word, text.trim_end()),
+                direction: Direction::detect(&word),
+            });
+            if entries.len().is_multiple_of(5000) {
+                progress(entries.len());
+            }
+        }
+        progress(entries.len());
+        if entries.is_empty() {
+            return Err(DictError::Parse(self.ifo_path.clone()));
+        }
+        Ok(entries)
+    }
+}
+
+/// The `.ifo` files of `path`: the file itself or the ones into a directory
+pub fn find_ifo_files(path: &Path) -> Vec<PathBuf> {
+    if !path.is_dir() {
+        return vec![path.to_path_buf()];
+    }
+    let mut ifo_paths: Vec<PathBuf> = fs::read_dir(path)
+        .map(|read_dir| {
+            read_dir
+                .filter_map(|dir_entry| dir_entry.ok().map(|dir_entry| dir_entry.path()))
+                .filter(|file| file.extension().is_some_and(|extension| extension == "ifo"))
+                .collect()
+        })
+        .unwrap_or_default();
+    ifo_paths.sort();
+    ifo_paths
+}
+
+/// Read a whole file, decompressing gzip (and dictzip, which is gzip
+/// with random access) files ending with `.gz` or `.dz`
+fn read_file(path: &Path) -> Result<Vec<u8>, DictError> {
+    let compressed = path
+        .extension()
+        .is_some_and(|extension| extension == "gz" || extension == "dz");
+    let mut data: Vec<u8> = Vec::new();
+    File::open(path)
+        .and_then(|file| {
+            if compressed {
+                GzDecoder::new(file).read_to_end(&mut data)
+            } else {
+                let mut file = file;
+                file.read_to_end(&mut data)
+            }
+        })
+        .map_err(|err| io_error(path, err))?;
+    Ok(data)
+}
+
+fn format_error(path: &Path, message: &str) -> DictError {
+    DictError::Format(path.to_path_buf(), message.to_string())
+}
+
+/// Big endian unsigned number
+fn read_be(bytes: &[u8]) -> usize {
+    bytes
+        .iter()
+        .fold(0usize, |number, &byte| (number << 8) | byte as usize)
+}
+
+/// Read a data field of `field_type` at `position`.
+/// Returns its text, if it has one, and the position of the next field.
+fn read_field(data: &[u8], position: usize, field_type: char, last: bool) -> (Option<String>, usize) {
+    let position = position.min(data.len());
+    let (bytes, next) = if field_type.is_lowercase() {
+        // Text, terminated with '\0'
+        match data[position..].iter().position(|&byte| byte == 0) {
+            Some(len) if !last => (&data[position..position + len], position + len + 1),
+            _ => (&data[position..], data.len()),
+        }
+    } else if last {
+        (&data[position..], data.len())
+    } else {
+        // Binary data, prefixed with its size
+        let size_end = (position + 4).min(data.len());
+        let size = read_be(&data[position..size_end]);
+        let end = (size_end + size).min(data.len());
+        (&data[size_end..end], end)
+    };
+    let text = String::from_utf8_lossy(bytes);
+    let field = match field_type {
+        // Plain text, locale text, WordNet, YinBiao or KANA
+        'm' | 'l' | 'n' | 'y' | 'k' => Some(text.into_owned()),
+        // Phonetic transcription
+        't' => Some(format!("[{}]", text)),
+        // Pango markup, HTML, XDXF, Powerword and resource lists
+        'g' | 'h' | 'x' | 'w' | 'r' => Some(strip_markup(&text)),
+        // Binary data: pictures, sounds, ...
+        _ => None,
+    };
+    (field, next)
+}
+
+/// Plain text of HTML-like markup: tags are removed, line breaks are kept
+fn strip_markup(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    let mut tag: Option<String> = None;
+    for c in markup.chars() {
+        match (c, tag.as_mut()) {
+            ('<', None) => tag = Some(String::new()),
+            ('>', Some(_)) => {
+                let name = tag.take().unwrap_or_default().to_lowercase();
+                let name = name.trim_start_matches('/');
+                if name.starts_with("br") || name.starts_with("p") || name.starts_with("div")
+                    || name.starts_with("li")
+                {
+                    text.push('\n');
+                }
+            }
+            (c, Some(tag)) => tag.push(c),
+            (c, None) => text.push(c),
+        }
+    }
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&nbsp;", " ")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// A new directory for the files of one test
+    fn test_dir() -> PathBuf {
+        static TEST_DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = ::std::env::temp_dir().join(format!(
+            "rbedic-stardict-test-{}-{}",
+            process::id(),
+            TEST_DIRS.fetch_add(1, Ordering::SeqCst)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn ifo(wordcount: usize, extra_lines: &str) -> String {
+        format!(
+            "{}\nversion=2.4.2\nbookname=Test\nwordcount={}\n{}",
+            IFO_MAGIC, wordcount, extra_lines
+        )
+    }
+
+    /// `.idx` data of `words` with 32 bit offsets
+    fn idx(words: &[(&str, u32, u32)]) -> Vec<u8> {
+        let mut data: Vec<u8> = Vec::new();
+        for &(word, offset, size) in words {
+            data.extend_from_slice(word.as_bytes());
+            data.push(0);
+            data.extend_from_slice(&offset.to_be_bytes());
+            data.extend_from_slice(&size.to_be_bytes());
+        }
+        data
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read(dir: &Path) -> Result<Vec<DictDB>, DictError> {
+        StarDict::open(&dir.join("test.ifo"))?.read(&mut |_| ())
+    }
+
+    #[test]
+    fn ifo_files() {
+        let dir = test_dir();
+        let ifo_path = dir.join("test.ifo");
+        fs::write(&ifo_path, ifo(2, "idxoffsetbits=64\nsametypesequence=tm\n")).unwrap();
+        let stardict = StarDict::open(&ifo_path).unwrap();
+        assert_eq!(stardict.bookname, "Test");
+        assert_eq!(stardict.wordcount, 2);
+        assert_eq!(stardict.idx_offset_bits, 64);
+        assert_eq!(stardict.same_type_sequence, Some("tm".to_string()));
+
+        fs::write(&ifo_path, format!("{}\nwordcount=1\n", IFO_MAGIC)).unwrap();
+        assert_eq!(StarDict::open(&ifo_path).unwrap().bookname, "test");
+        fs::write(&ifo_path, ifo(1, "idxoffsetbits=16\n")).unwrap();
+        assert!(matches!(StarDict::open(&ifo_path), Err(DictError::Format(..))));
+        fs::write(&ifo_path, "bookname=Test\n").unwrap();
+        assert!(matches!(StarDict::open(&ifo_path), Err(DictError::Format(..))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plain_dict() {
+        let dir = test_dir();
+        fs::write(dir.join("test.ifo"), ifo(2, "sametypesequence=tm\n")).unwrap();
+        let dict = b"buk\0a book\nbooksk\xc9\x99t\0a cat";
+        fs::write(dir.join("test.dict"), &dict[..]).unwrap();
+        fs::write(dir.join("test.idx"), idx(&[("book", 0, 16), ("cat", 16, 10)])).unwrap();
+        let entries = read(&dir).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].word, "BOOK");
+        assert_eq!(entries[0].translation, "book\n[buk]\na book\nbooks\n");
+        assert_eq!(entries[0].direction, Direction::EnBg);
+        assert_eq!(entries[1].translation, "cat\n[k\u{259}t]\na cat\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn dictzip() {
+        let dir = test_dir();
+        fs::write(dir.join("test.ifo"), ifo(1, "")).unwrap();
+        // Without sametypesequence, each field starts with its type
+        let dict = b"m\xd0\xba\xd0\xbd\xd0\xb8\xd0\xb3\xd0\xb0\0h<b>tome</b><br>volume\0";
+        fs::write(dir.join("test.dict.dz"), gzip(dict)).unwrap();
+        fs::write(dir.join("test.idx.gz"), gzip(&idx(&[("book", 0, dict.len() as u32)]))).unwrap();
+        let entries = read(&dir).unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].translation, "book\nкнига\ntome\nvolume\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_files() {
+        let dir = test_dir();
+        fs::write(dir.join("test.ifo"), ifo(1, "sametypesequence=m\n")).unwrap();
+        fs::write(dir.join("test.dict"), b"a book").unwrap();
+        // No .idx file
+        assert!(matches!(read(&dir), Err(DictError::NotFound(_))));
+
+        let mut truncated = idx(&[("book", 0, 6)]);
+        truncated.pop();
+        fs::write(dir.join("test.idx"), &truncated).unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Format(..))));
+        fs::write(dir.join("test.idx"), b"book").unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Format(..))));
+        fs::write(dir.join("test.idx"), idx(&[("book", 2, 6)])).unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Format(..))));
+        fs::write(dir.join("test.idx"), b"").unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Parse(_))));
+
+        // Offset and size overflow
+        fs::write(dir.join("test.ifo"), ifo(1, "idxoffsetbits=64\nsametypesequence=m\n")).unwrap();
+        let mut overflow: Vec<u8> = b"book\0".to_vec();
+        overflow.extend_from_slice(&u64::MAX.to_be_bytes());
+        overflow.extend_from_slice(&6u32.to_be_bytes());
+        fs::write(dir.join("test.idx"), &overflow).unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Format(..))));
+
+        // Not gzip
+        fs::write(dir.join("test.ifo"), ifo(1, "sametypesequence=m\n")).unwrap();
+        fs::remove_file(dir.join("test.dict")).unwrap();
+        fs::write(dir.join("test.dict.dz"), b"a book").unwrap();
+        fs::write(dir.join("test.idx"), idx(&[("book", 0, 6)])).unwrap();
+        assert!(matches!(read(&dir), Err(DictError::Io(..))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn markup() {
+        assert_eq!(strip_markup("<b>book</b><br/>a &lt;thing&gt; &amp; more"), "book\na <thing> & more");
+        assert_eq!(strip_markup("<div>one</div><div>two</div>"), "\none\n\ntwo\n");
+    }
+}
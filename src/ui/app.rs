@@ -10,17 +10,59 @@ use gtk;
 use gtk::*;
 use gdk;
 use gdk::enums::key;
+use std::cmp;
 use std::process;
 use std::rc::Rc;
-use std::sync::Mutex;
-use std::sync::atomic::{AtomicBool, AtomicIsize, Ordering};
+use std::sync::{mpsc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicIsize, AtomicUsize, Ordering};
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::thread;
 //use log;
 
-use super::{Content, Header};
-use database::DictDB;
+use super::{Content, Header, Navigation, NavigationItem, QuizDialog, ReviewDialog, SearchMode};
+use rbedic::{Charset, Deck, DictDB, DictError, DictKey, Dictionaries, Direction, Grade, HistoryFile,
+             HistoryRecord, LoadProgress, StarDict};
+use rbedic::{layout, pattern, quiz, review};
+use rbedic::quiz::QuizMode;
+use regex::Regex;
 
-const VERSION: &'static str = env!("CARGO_PKG_VERSION");
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Number of similar words shown when nothing is found
+const FUZZY_LIMIT: usize = 20;
+/// Maximal number of entries found by full-text search
+const FULLTEXT_LIMIT: usize = 1000;
+/// Number of entries matched against a pattern per idle call
+const PATTERN_CHUNK: usize = 5000;
+/// Interval in milliseconds of checking for the progress of loading the dictionaries
+const LOAD_POLL_INTERVAL: u32 = 100;
+/// Back and forward buttons of the mouse
+const MOUSE_BUTTON_BACK: u32 = 8;
+const MOUSE_BUTTON_FORWARD: u32 = 9;
+/// Number of questions of a quiz
+const QUIZ_LENGTH: usize = 10;
+/// Seconds after the first counted lookup before the counts are written to the history file
+const LOOKUP_FLUSH_INTERVAL: u32 = 30;
+
+/// A history word which can be restored
+pub struct DeletedWord {
+    record: HistoryRecord,
+    /// Position into the history file
+    file_index: usize,
+    /// Position into `App::history_dictdb_unsorted`
+    list_index: usize,
+}
+
+/// Messages from the thread loading the dictionaries
+enum LoadMessage {
+    Progress(LoadProgress),
+    /// The dictionaries are loaded, the indices of the typo tolerant and
+    /// full-text search are being built
+    Indexing,
+    /// An extra dictionary which can not be loaded
+    SourceError(DictError),
+    Done(Result<Dictionaries, DictError>),
+}
 
 #[derive(Clone)]
 pub struct App {
@@ -29,8 +71,50 @@ pub struct App {
     pub content: Content,
     pub history_dictdb: Rc<Mutex<Vec<DictDB>>>,
     pub history_dictdb_unsorted: Rc<Mutex<Vec<DictDB>>>,
+    /// File where the added words are recorded
+    pub history_file: HistoryFile,
+    /// Print the added words to stdout too, as the history file did before
+    pub echo_stdout: bool,
+    /// Word whose lookup was counted last, so that repeated searches count once
+    pub counted_lookup: Rc<Mutex<Option<DictDB>>>,
+    /// Lookups of history words not written to the history file yet, see `flush_lookups()`
+    pub pending_lookups: Rc<Mutex<HashMap<(String, Direction), u32>>>,
+    /// True while the words list shows the history, where words can be deleted
+    pub history_mode: Rc<AtomicBool>,
+    /// Personal notes of the history words, by word and direction
+    pub history_notes: Rc<Mutex<HashMap<(String, Direction), String>>>,
+    /// Deleted history words, the last one is restored first
+    pub history_undo: Rc<Mutex<Vec<DeletedWord>>>,
+    /// Review state of the history words, `None` if the review file can not be read
+    pub deck: Rc<Mutex<Option<Deck>>>,
     pub selection_isize: Rc<AtomicIsize>,
-    pub searched_hash: Rc<Mutex<HashMap<String, String>>>,
+    /// Entries shown into the words list, by word
+    pub searched_hash: Rc<Mutex<HashMap<String, DictDB>>>,
+    pub data_dir: Option<String>,
+    /// Encoding of the dictionary files, detected if `None`
+    pub encoding: Option<Charset>,
+    /// `.ifo` files of the StarDict dictionaries loaded next to the bedic ones
+    pub stardict_paths: Vec<PathBuf>,
+    pub dictionaries: Rc<Mutex<Dictionaries>>,
+    /// Label shown next to the found words instead of their direction,
+    /// e.g. the name of the searched StarDict dictionary
+    pub result_label: Rc<Mutex<Option<String>>>,
+    /// Names of the extra dictionaries of the words found into them in automatic mode,
+    /// see `search_extra()`
+    pub extra_labels: Rc<Mutex<HashMap<String, String>>>,
+    /// True until the dictionaries are loaded
+    pub loading: Rc<AtomicBool>,
+    pub suggested_word: Rc<Mutex<Option<String>>>,
+    /// Incremented by each search, stops the streaming of outdated results
+    pub search_generation: Rc<AtomicUsize>,
+    /// Number of words shown at once into the words list
+    pub display_limit: usize,
+    /// Shows the next page of results, when the "load more" row is activated
+    pub load_more: Rc<Mutex<Option<std::boxed::Box<dyn Fn()>>>>,
+    /// Searches of the session, to go back and forward to
+    pub navigation: Rc<Mutex<Navigation>>,
+    /// True while a search is restored, the changes of the search bar do not search then
+    pub navigating: Rc<AtomicBool>,
 }
 
 /// A wrapped `App` which provides the capability to execute the program.
@@ -41,11 +125,20 @@ impl ConnectedApp {
     pub fn then_execute(self) {
         self.0.window.show_all();
         gtk::main();
+        self.0.flush_lookups();
     }
 }
 
 impl App {
-    pub fn new(history_file_path: &str, prevents_reading_history_file_bool: bool ) -> App {
+    pub fn new(
+        history_file_path: &str,
+        prevents_reading_history_file_bool: bool,
+        echo_stdout: bool,
+        data_dir: Option<String>,
+        encoding: Option<Charset>,
+        stardict_paths: Vec<PathBuf>,
+        display_limit: usize,
+    ) -> App {
         // Initialize GTK before proceeding.
         if gtk::init().is_err() {
             eprintln!("failed to initialize GTK Application");
@@ -84,14 +177,54 @@ impl App {
 
         let selection_isize = Rc::new(AtomicIsize::new(0));
         let searched_hash = Rc::new(Mutex::new(HashMap::new()));
+        let dictionaries = Rc::new(Mutex::new(Dictionaries::default()));
+        let loading = Rc::new(AtomicBool::new(false));
+        let result_label = Rc::new(Mutex::new(None));
+        let suggested_word = Rc::new(Mutex::new(None));
+        let search_generation = Rc::new(AtomicUsize::new(0));
+        let load_more = Rc::new(Mutex::new(None));
+        let navigation = Rc::new(Mutex::new(Navigation::default()));
+        let navigating = Rc::new(AtomicBool::new(false));
 
         // Loading history from file
+        let history_file = HistoryFile::new(history_file_path);
         let mut vec_history_db: Vec<DictDB> = Vec::new();
+        let mut history_notes: HashMap<(String, Direction), String> = HashMap::new();
+        // True if the words of the history file are known
+        let mut history_read = false;
         if prevents_reading_history_file_bool {
             debug!("Do not read history file.");
         } else {
             trace!("Reading the history file.");
-            vec_history_db = DictDB::new_history(&history_file_path);
+            let records = match history_file.load() {
+                Ok(records) => {
+                    history_read = true;
+                    records
+                }
+                Err(err) => {
+                    error!("Can not read history file {:?}: {}", history_file.path(), err);
+                    Vec::new()
+                }
+            };
+            for record in records {
+                if !record.note.is_empty() {
+                    history_notes.insert((record.headword.clone(), record.direction), record.note.clone());
+                }
+                vec_history_db.push(record.to_dict_db());
+            }
+        }
+
+        let mut deck = Deck::open(history_file.path())
+            .map_err(|err| error!("Can not read review file of {:?}: {}", history_file.path(), err))
+            .ok();
+        // Without the history, all cards would seem to be of deleted words
+        match deck {
+            Some(ref mut deck) if history_read => {
+                if let Err(err) = deck.prune(&vec_history_db) {
+                    error!("Can not write review file of {:?}: {}", history_file.path(), err);
+                }
+            }
+            _ => (),
         }
 
         let vec_history_db_clonned = vec_history_db.clone();
@@ -109,8 +242,29 @@ impl App {
             content,
             history_dictdb,
             history_dictdb_unsorted,
+            history_file,
+            echo_stdout,
+            counted_lookup: Rc::new(Mutex::new(None)),
+            pending_lookups: Rc::new(Mutex::new(HashMap::new())),
+            history_mode: Rc::new(AtomicBool::new(false)),
+            history_notes: Rc::new(Mutex::new(history_notes)),
+            history_undo: Rc::new(Mutex::new(Vec::new())),
+            deck: Rc::new(Mutex::new(deck)),
             selection_isize,
             searched_hash,
+            data_dir,
+            encoding,
+            stardict_paths,
+            dictionaries,
+            result_label,
+            extra_labels: Rc::new(Mutex::new(HashMap::new())),
+            loading,
+            suggested_word,
+            search_generation,
+            display_limit,
+            load_more,
+            navigation,
+            navigating,
         }
     }
 
@@ -134,11 +288,19 @@ impl App {
             self.about_event();
             self.history_event();
             self.add_2_history_event(history_dictdb.clone(), history_dictdb_unsorted.clone());
+            self.suggestion_event();
+            self.link_event();
+            self.navigation_event();
+            self.history_edit_event();
+            self.review_event();
+            self.quiz_event();
             self.key_events(fullscreen);
+            self.queue_typed_text_event();
         }
         {
             // Enabled initially the history button, if history from file was loaded
             let app_clonned = self.clone();
+            app_clonned.history_mode.store(true, Ordering::SeqCst);
             {
                 debug!("history_event: Clear searched_hash");
                 let mut searched_hash_locked = app_clonned.searched_hash.lock().unwrap();
@@ -166,51 +328,808 @@ impl App {
         // Load dictionaries
         let app = self;
         let app_clonned = app.clone();
-        let vec_dict_db = DictDB::new();
+        app.load_dictionaries();
         content_clonned
             .s_bar
             .search_entry
             .connect_changed(move |search_selection| {
+                if app_clonned.loading.load(Ordering::SeqCst) {
+                    trace!("connect_changed: dictionaries are not loaded, queue the text");
+                    return;
+                }
+                if app_clonned.navigating.load(Ordering::SeqCst) {
+                    return;
+                }
                 let search_text_len = search_selection.get_text_length();
                 if search_text_len > 0 && search_text_len < 51 {
                     let search_text = search_selection.get_text();
-                    {
-                        debug!("connect_events: Clear searched_hash");
-                        let mut searched_hash_locked = app_clonned.searched_hash.lock().unwrap();
-                        searched_hash_locked.clear();
-                    }
                     match search_text {
-                        Some(txt) => {
-                            trace!("Search txt: {:?}", txt);
-                            // Search into databases
-                            match DictDB::search(&txt, &vec_dict_db) {
-                                Ok(vec_result) => {
-                                    // Success
-                                    trace!("Search into DB is Ok: {:?}", vec_result);
-                                    // Write to GUI
-                                    app_clonned.selection(&vec_result, false, false);
-                                }
-                                Err(vec_result_err) => {
-                                    trace!(
-                                        "Error: Can not find the exact word: {:?}",
-                                        vec_result_err
-                                    );
-                                    if vec_result_err.len() == 0 {
-                                        button_add_2_history.set_sensitive(false);
-                                    };
-                                    // Write to GUI
-                                    app_clonned.selection(&vec_result_err, false, false);
-                                }
-                            };
-                        }
+                        Some(txt) => app_clonned.search(&txt),
                         None => trace!("None"),
                     };
                 }
             });
+        // Show the next page of results when the "load more" row is activated
+        let app_clonned = app.clone();
+        content_clonned
+            .inner_paned
+            .words
+            .tree_view
+            .connect_row_activated(move |_, path, _| {
+                let words = &app_clonned.content.inner_paned.words;
+                let is_info_row = words
+                    .tree_store
+                    .get_iter(path)
+                    .and_then(|iter| words.tree_store.get_value(&iter, 0).get::<String>())
+                    .map(|word| word.is_empty())
+                    .unwrap_or(false);
+                if is_info_row {
+                    let load_more = app_clonned.load_more.lock().unwrap().take();
+                    if let Some(load_more) = load_more {
+                        trace!("connect_row_activated: load more");
+                        words.remove_last_row();
+                        load_more();
+                    }
+                }
+            });
+        // Repeat the search when the search mode or the translation direction is changed
+        let app_clonned = app.clone();
+        content_clonned
+            .s_bar
+            .mode
+            .connect_changed(move |_| app_clonned.repeat_search());
+        let app_clonned = app.clone();
+        content_clonned
+            .s_bar
+            .direction
+            .connect_changed(move |_| app_clonned.repeat_search());
         // Wrap the `App` within `ConnectedApp` to enable the developer to execute the program.
         ConnectedApp(app)
     }
 
+    /// Search again for the text into the search entry
+    fn repeat_search(&self) {
+        if self.loading.load(Ordering::SeqCst) || self.navigating.load(Ordering::SeqCst) {
+            return;
+        }
+        let search_entry = &self.content.s_bar.search_entry;
+        if search_entry.get_text_length() > 0 {
+            if let Some(txt) = search_entry.get_text() {
+                self.search(&txt);
+            }
+        }
+    }
+
+    /// The dictionary selected for searching `txt` in `mode`. In auto mode, the bedic
+    /// dictionary is detected by the script of `txt` (for full-text search, the one
+    /// translating to this script). Words are then searched into the extra dictionaries
+    /// too, see `search_extra()`.
+    fn selected_dictionary(&self, txt: &str, mode: SearchMode) -> DictKey {
+        let s_bar = &self.content.s_bar;
+        if let Some(index) = s_bar.extra_dictionary() {
+            return DictKey::Extra(index);
+        }
+        match (s_bar.direction(), mode) {
+            (Some(direction), _) => DictKey::Bedic(direction),
+            (None, SearchMode::FullText) => DictKey::Bedic(Direction::detect(txt).opposite()),
+            (None, _) => DictKey::Bedic(Direction::detect(txt)),
+        }
+    }
+
+    /// Label of `dict_db` into the words list
+    fn entry_label(&self, dict_db: &DictDB) -> String {
+        if let Some(label) = self.extra_labels.lock().unwrap().get(&dict_db.word) {
+            return label.clone();
+        }
+        match *self.result_label.lock().unwrap() {
+            Some(ref label) => label.clone(),
+            None => dict_db.direction.label().to_string(),
+        }
+    }
+
+    /// Add the entries of the extra (e.g. StarDict) dictionaries starting with `txt` to
+    /// `search_result` of a bedic dictionary. The exact matches come first, those of the
+    /// bedic dictionary before the extra ones. Words already found are left out, as the
+    /// words list shows one entry per word. Returns `Ok` if any dictionary has `txt`.
+    fn search_extra(
+        &self,
+        txt: &str,
+        search_result: Result<Vec<DictDB>, Vec<DictDB>>,
+        dictionaries: &Dictionaries,
+    ) -> Result<Vec<DictDB>, Vec<DictDB>> {
+        let mut extra_labels = self.extra_labels.lock().unwrap();
+        let (mut exact, mut prefixed) = match search_result {
+            Ok(vec_result) => (vec_result, Vec::new()),
+            Err(vec_result) => (Vec::new(), vec_result),
+        };
+        let mut extra_exact = Vec::new();
+        for dictionary in &dictionaries.extra {
+            let (vec_result, found) = match dictionary.search(txt) {
+                Ok(vec_result) => (vec_result, &mut extra_exact),
+                Err(vec_result) => (vec_result, &mut prefixed),
+            };
+            for dict_db in vec_result {
+                let is_new = |found: &[DictDB]| !found.iter().any(|entry| entry.word == dict_db.word);
+                if is_new(&exact) && is_new(found) && !extra_labels.contains_key(&dict_db.word) {
+                    extra_labels.insert(dict_db.word.clone(), dictionary.name.clone());
+                    found.push(dict_db);
+                }
+            }
+        }
+        let is_exact = !exact.is_empty() || !extra_exact.is_empty();
+        exact.append(&mut extra_exact);
+        exact.append(&mut prefixed);
+        if is_exact {
+            Ok(exact)
+        } else {
+            Err(exact)
+        }
+    }
+
+    /// Search into dictionaries of the selected direction and write the result to GUI
+    fn search(&self, txt: &str) {
+        let button_add_2_history = self.content.s_bar.add_2_history.clone();
+        {
+            debug!("search: Clear searched_hash");
+            let mut searched_hash_locked = self.searched_hash.lock().unwrap();
+            searched_hash_locked.clear();
+        }
+        trace!("Search txt: {:?}", txt);
+        self.search_generation.fetch_add(1, Ordering::SeqCst);
+        self.history_mode.store(false, Ordering::SeqCst);
+        *self.load_more.lock().unwrap() = None;
+        self.content.inner_paned.translation.hide_suggestion();
+        let mode = self.content.s_bar.mode();
+        let key = self.selected_dictionary(txt, mode);
+        self.extra_labels.lock().unwrap().clear();
+        *self.result_label.lock().unwrap() = match key {
+            DictKey::Extra(index) => {
+                let dictionaries = self.dictionaries.lock().unwrap();
+                dictionaries.extra.get(index).map(|dictionary| dictionary.name.clone())
+            }
+            DictKey::Bedic(_) => None,
+        };
+        match mode {
+            SearchMode::FullText => {
+                self.fulltext_selection(txt, key);
+                return;
+            }
+            SearchMode::Pattern => {
+                self.pattern_selection(txt, key);
+                return;
+            }
+            SearchMode::Words => (),
+        }
+        // Search into databases
+        let search_result = {
+            let dictionaries = self.dictionaries.lock().unwrap();
+            let search_result = match dictionaries.by_key(key) {
+                Some(dictionary) => dictionary.search(txt),
+                None => Err(Vec::new()),
+            };
+            // In automatic mode, the words missing from the bedic dictionary may be
+            // into the extra ones
+            match (key, self.content.s_bar.direction()) {
+                (DictKey::Bedic(_), None) => self.search_extra(txt, search_result, &dictionaries),
+                _ => search_result,
+            }
+        };
+        match search_result {
+            Ok(vec_result) => {
+                // Success
+                trace!("Search into DB is Ok: {:?}", vec_result);
+                if let Some(found) = vec_result.first() {
+                    self.count_lookup(found);
+                }
+                // Write to GUI
+                self.paged_selection(vec_result, Vec::new());
+            }
+            Err(vec_result_err) => {
+                trace!(
+                    "Error: Can not find the exact word: {:?}",
+                    vec_result_err
+                );
+                if vec_result_err.len() == 0 {
+                    button_add_2_history.set_sensitive(false);
+                    if let DictKey::Bedic(_) = key {
+                        self.suggest_layout_correction(txt);
+                    }
+                    if self.fuzzy_selection(txt, key) {
+                        return;
+                    }
+                };
+                // Write to GUI
+                self.paged_selection(vec_result_err, Vec::new());
+            }
+        };
+    }
+
+    /// Count the lookup of `found`, the exact match of a search, if the word is into
+    /// the history. The counts are written to the history file a while later, together.
+    fn count_lookup(&self, found: &DictDB) {
+        {
+            let mut counted_lookup = self.counted_lookup.lock().unwrap();
+            if counted_lookup.as_ref() == Some(found) {
+                return;
+            }
+            *counted_lookup = Some(found.clone());
+        }
+        if history_position(&self.history_dictdb.lock().unwrap(), &found.word, found.direction).is_none() {
+            return;
+        }
+        let first_pending = {
+            let mut pending_lookups = self.pending_lookups.lock().unwrap();
+            *pending_lookups.entry((found.word.clone(), found.direction)).or_insert(0) += 1;
+            pending_lookups.len() == 1
+        };
+        debug!("count_lookup: {:?}", found.word);
+        if first_pending {
+            let app = self.clone();
+            gtk::timeout_add_seconds(LOOKUP_FLUSH_INTERVAL, move || {
+                app.flush_lookups();
+                Continue(false)
+            });
+        }
+    }
+
+    /// Add the pending lookup counts to the history file, with a single update
+    fn flush_lookups(&self) {
+        let pending_lookups: HashMap<(String, Direction), u32> =
+            self.pending_lookups.lock().unwrap().drain().collect();
+        if pending_lookups.is_empty() {
+            return;
+        }
+        let updated = self.history_file.update(|records| {
+            for record in records.iter_mut() {
+                if let Some(lookups) = pending_lookups.get(&(record.headword.clone(), record.direction)) {
+                    record.lookups += lookups;
+                }
+            }
+        });
+        match updated {
+            Ok(_) => debug!("flush_lookups: {} words", pending_lookups.len()),
+            Err(err) => error!("Can not write history file {:?}: {}", self.history_file.path(), err),
+        }
+    }
+
+    /// Stream the words matching the pattern `txt` into the words list,
+    /// a page of `display_limit` words at a time.
+    fn pattern_selection(&self, txt: &str, key: DictKey) {
+        let right_buff = self.content.inner_paned.translation.buff.clone();
+        self.content.inner_paned.words.tree_store.clear();
+        self.content.s_bar.add_2_history.set_sensitive(false);
+        let regex = match pattern::compile(txt) {
+            Ok(regex) => regex,
+            Err(err) => {
+                right_buff.set_text(&format!("Invalid pattern: {}", err));
+                return;
+            }
+        };
+        right_buff.set_text("");
+        self.stream_pattern(Rc::new(regex), key, 0, 0);
+    }
+
+    /// Stream the words matching `regex` from entry `position` on, a chunk of the
+    /// dictionary per idle call, until `display_limit` more words are shown.
+    /// `found` is the number of words already shown.
+    fn stream_pattern(&self, regex: Rc<Regex>, key: DictKey, position: usize, found: usize) {
+        let generation = self.search_generation.load(Ordering::SeqCst);
+        let app = self.clone();
+        let mut position = position;
+        let mut found = found;
+        let page_end = found + self.display_limit;
+        gtk::idle_add(move || {
+            if app.search_generation.load(Ordering::SeqCst) != generation {
+                trace!("stream_pattern: outdated search, stop streaming");
+                return Continue(false);
+            }
+            let mut chunk: Vec<DictDB> = Vec::new();
+            let mut more_results = false;
+            let finished: bool;
+            {
+                let dictionaries = app.dictionaries.lock().unwrap();
+                let dictionary = match dictionaries.by_key(key) {
+                    Some(dictionary) => dictionary,
+                    None => return Continue(false),
+                };
+                let end = cmp::min(position + PATTERN_CHUNK, dictionary.len());
+                while position < end {
+                    if regex.is_match(dictionary.word(position)) {
+                        if found + chunk.len() == page_end {
+                            more_results = true;
+                            break;
+                        }
+                        chunk.push(dictionary.entry(position));
+                    }
+                    position += 1;
+                }
+                finished = more_results || position == dictionary.len();
+            }
+            if !chunk.is_empty() {
+                if found == 0 {
+                    app.selection(&chunk, false, false);
+                } else {
+                    app.append_selection(&chunk);
+                }
+                found += chunk.len();
+            }
+            if more_results {
+                app.content
+                    .inner_paned
+                    .words
+                    .append_info_row("\u{2026} more words, double-click to load");
+                let app_more = app.clone();
+                let regex = regex.clone();
+                *app.load_more.lock().unwrap() = Some(std::boxed::Box::new(move || {
+                    app_more.stream_pattern(regex.clone(), key, position, found)
+                }));
+            }
+            if finished {
+                debug!("stream_pattern: {} words, more results: {}", found, more_results);
+                Continue(false)
+            } else {
+                Continue(true)
+            }
+        });
+    }
+
+    /// Show `vec_dict_db` a page of `display_limit` entries at a time.
+    /// `infos` (e.g. snippets) are shown into the info column of the entries.
+    fn paged_selection(&self, vec_dict_db: Vec<DictDB>, infos: Vec<String>) {
+        let page_end = cmp::min(self.display_limit, vec_dict_db.len());
+        self.selection(&vec_dict_db[..page_end].to_vec(), false, false);
+        self.content
+            .inner_paned
+            .words
+            .set_info(0, &infos[..cmp::min(page_end, infos.len())]);
+        self.load_more_row(Rc::new(vec_dict_db), Rc::new(infos), page_end);
+    }
+
+    /// Append a "load more" row for the entries of `vec_dict_db` after `shown`
+    fn load_more_row(&self, vec_dict_db: Rc<Vec<DictDB>>, infos: Rc<Vec<String>>, shown: usize) {
+        let remaining = vec_dict_db.len() - shown;
+        if remaining == 0 {
+            return;
+        }
+        self.content.inner_paned.words.append_info_row(&format!(
+            "\u{2026} {} more words, double-click to load",
+            remaining
+        ));
+        let app = self.clone();
+        *self.load_more.lock().unwrap() = Some(std::boxed::Box::new(move || {
+            let page_end = cmp::min(shown + app.display_limit, vec_dict_db.len());
+            app.append_selection(&vec_dict_db[shown..page_end]);
+            if infos.len() > shown {
+                app.content
+                    .inner_paned
+                    .words
+                    .set_info(shown, &infos[shown..cmp::min(page_end, infos.len())]);
+            }
+            app.load_more_row(vec_dict_db.clone(), infos.clone(), page_end);
+        }));
+    }
+
+    /// Append entries to the words list filled by `selection()`
+    fn append_selection(&self, vec_dict_db: &[DictDB]) {
+        let tree_store = &self.content.inner_paned.words.tree_store;
+        let mut searched_hash_locked = self.searched_hash.lock().unwrap();
+        for i in vec_dict_db {
+            tree_store.insert_with_values(
+                None,
+                None,
+                &[0, 1],
+                &[&format!("{}", &i.word), &self.entry_label(i)],
+            );
+            searched_hash_locked.insert(i.word.clone(), i.clone());
+        }
+    }
+
+    /// Show words whose translation contains `txt`, with a snippet around the match
+    fn fulltext_selection(&self, txt: &str, key: DictKey) {
+        let fulltext_matches = {
+            let dictionaries = self.dictionaries.lock().unwrap();
+            dictionaries
+                .by_key(key)
+                .map(|dictionary| dictionary.fulltext_search(txt, FULLTEXT_LIMIT))
+                .unwrap_or_default()
+        };
+        debug!("fulltext_selection: {} entries", fulltext_matches.len());
+        if fulltext_matches.is_empty() {
+            self.content.s_bar.add_2_history.set_sensitive(false);
+        }
+        let vec_fulltext: Vec<DictDB> = fulltext_matches.iter().map(|m| m.entry.clone()).collect();
+        let snippets: Vec<String> = fulltext_matches.iter().map(|m| m.snippet.clone()).collect();
+        self.paged_selection(vec_fulltext, snippets);
+    }
+
+    /// Show words similar to the not found `txt`, marked with their edit distance.
+    /// Returns false if there are no similar words.
+    fn fuzzy_selection(&self, txt: &str, key: DictKey) -> bool {
+        let fuzzy_matches = {
+            let dictionaries = self.dictionaries.lock().unwrap();
+            dictionaries
+                .by_key(key)
+                .map(|dictionary| dictionary.fuzzy_search(txt, FUZZY_LIMIT))
+                .unwrap_or_default()
+        };
+        debug!("fuzzy_selection: {} similar words", fuzzy_matches.len());
+        if fuzzy_matches.is_empty() {
+            return false;
+        }
+        let vec_fuzzy: Vec<DictDB> = fuzzy_matches.iter().map(|m| m.entry.clone()).collect();
+        let markers: Vec<String> = fuzzy_matches
+            .iter()
+            .map(|m| format!("\u{2248}{}", m.distance))
+            .collect();
+        self.selection(&vec_fuzzy, false, false);
+        self.content.inner_paned.words.set_info(0, &markers);
+        true
+    }
+
+    /// Retype the not found `txt` with another keyboard layout and
+    /// show the "Did you mean ..." banner, if it is found so.
+    fn suggest_layout_correction(&self, txt: &str) {
+        let corrected = {
+            let dictionaries = self.dictionaries.lock().unwrap();
+            layout::correct(txt, &dictionaries)
+        };
+        debug!("suggest_layout_correction: {:?} -> {:?}", txt, corrected);
+        if let Some(ref word) = corrected {
+            self.content.inner_paned.translation.show_suggestion(word);
+        }
+        *self.suggested_word.lock().unwrap() = corrected;
+    }
+
+    /// Program "Did you mean ..." banner: search for the suggested word
+    fn suggestion_event(&self) {
+        let app = self.clone();
+        let s_bar = self.content.s_bar.clone();
+        let suggested_word = self.suggested_word.clone();
+        self.content
+            .inner_paned
+            .translation
+            .suggestion
+            .connect_clicked(move |_| {
+                let word = suggested_word.lock().unwrap().take();
+                if let Some(word) = word {
+                    trace!("suggestion_event: search for {:?}", word);
+                    app.remember_search();
+                    // The suggestion is in the other alphabet
+                    s_bar.direction.set_active_id(Some("auto"));
+                    s_bar.search_entry.set_text(&word);
+                    s_bar.search_entry.grab_focus();
+                }
+            });
+    }
+
+    /// Show the entry `dict_db` into the translation pane, with the note of the word
+    /// if it has one. Glosses which are headwords of the dictionary of their alphabet
+    /// become links.
+    fn show_entry(&self, dict_db: &DictDB) {
+        let translation = dict_db.translation.as_str();
+        let headword = translation.lines().next().unwrap_or("").trim().to_string();
+        let note = self.history_notes.lock().unwrap().get(&(headword, dict_db.direction)).cloned();
+        // No links while the dictionaries are being replaced
+        let dictionaries = self.dictionaries.try_lock().ok();
+        let is_headword = |word: &str| match dictionaries {
+            Some(ref dictionaries) => dictionaries.get(Direction::detect(word)).contains(word),
+            None => false,
+        };
+        self.content
+            .inner_paned
+            .translation
+            .show_entry(translation, note.as_ref().map(|note| note.as_str()), &is_headword);
+    }
+
+    /// Program the links into the translation pane: search for the clicked word
+    fn link_event(&self) {
+        let app = self.clone();
+        self.content
+            .inner_paned
+            .translation
+            .text_view
+            .connect_button_release_event(move |_, event| {
+                if event.get_button() == 1 {
+                    if let Some(word) = app.content.inner_paned.translation.link_at_cursor() {
+                        app.follow_link(&word);
+                    }
+                }
+                Inhibit(false)
+            });
+    }
+
+    /// Program the navigation between the searches of the session: the back and
+    /// forward buttons, the back and forward mouse buttons, and the search entry,
+    /// where pressing Enter remembers the search
+    fn navigation_event(&self) {
+        let app = self.clone();
+        self.header.back.connect_clicked(move |_| app.go_back());
+        let app = self.clone();
+        self.header.forward.connect_clicked(move |_| app.go_forward());
+        let app = self.clone();
+        self.window.connect_button_press_event(move |_, event| match event.get_button() {
+            MOUSE_BUTTON_BACK => {
+                app.go_back();
+                Inhibit(true)
+            }
+            MOUSE_BUTTON_FORWARD => {
+                app.go_forward();
+                Inhibit(true)
+            }
+            _ => Inhibit(false),
+        });
+        let app = self.clone();
+        self.content
+            .s_bar
+            .search_entry
+            .connect_activate(move |_| app.remember_search());
+    }
+
+    /// The current search, to return to it later
+    fn navigation_item(&self) -> NavigationItem {
+        let s_bar = &self.content.s_bar;
+        NavigationItem {
+            text: s_bar.search_entry.get_text().unwrap_or_default(),
+            mode_id: s_bar.mode.get_active_id(),
+            direction_id: s_bar.direction.get_active_id(),
+            selected: self.content.inner_paned.words.selected_word(),
+        }
+    }
+
+    /// Remember the current search into the navigation stack, before leaving it
+    fn remember_search(&self) {
+        let current = self.navigation_item();
+        if current.text.is_empty() {
+            return;
+        }
+        trace!("remember_search: {:?}", current);
+        self.navigation.lock().unwrap().push(current);
+        self.update_navigation_buttons();
+    }
+
+    fn update_navigation_buttons(&self) {
+        let navigation = self.navigation.lock().unwrap();
+        self.header.back.set_sensitive(navigation.can_go_back());
+        self.header.forward.set_sensitive(navigation.can_go_forward());
+    }
+
+    /// Return to the previous search of the session
+    fn go_back(&self) {
+        let current = self.navigation_item();
+        let previous = self.navigation.lock().unwrap().back(current);
+        if let Some(item) = previous {
+            debug!("go_back: {:?}", item);
+            self.restore_search(&item);
+        }
+        self.update_navigation_buttons();
+    }
+
+    /// Return to the search left by `go_back()`
+    fn go_forward(&self) {
+        let current = self.navigation_item();
+        let next = self.navigation.lock().unwrap().forward(current);
+        if let Some(item) = next {
+            debug!("go_forward: {:?}", item);
+            self.restore_search(&item);
+        }
+        self.update_navigation_buttons();
+    }
+
+    /// Set the search bar to `item` and search once, then select the row of `item`
+    fn restore_search(&self, item: &NavigationItem) {
+        if self.loading.load(Ordering::SeqCst) {
+            return;
+        }
+        let s_bar = &self.content.s_bar;
+        self.navigating.store(true, Ordering::SeqCst);
+        if let Some(ref mode_id) = item.mode_id {
+            s_bar.mode.set_active_id(Some(mode_id.as_str()));
+        }
+        if let Some(ref direction_id) = item.direction_id {
+            s_bar.direction.set_active_id(Some(direction_id.as_str()));
+        }
+        s_bar.search_entry.set_text(&item.text);
+        self.navigating.store(false, Ordering::SeqCst);
+        self.repeat_search();
+        if let Some(ref word) = item.selected {
+            // Rows streamed by pattern search are not shown yet
+            if !self.content.inner_paned.words.select_word(word) {
+                debug!("restore_search: {:?} is not listed", word);
+            }
+        }
+        s_bar.search_entry.grab_focus();
+        s_bar.search_entry.set_position(-1);
+    }
+
+    /// Search for `word` into the dictionary of its alphabet, e.g. a Bulgarian gloss
+    /// into BG→EN, and remember the current search into the navigation stack
+    fn follow_link(&self, word: &str) {
+        debug!("follow_link: {:?}", word);
+        self.remember_search();
+        self.restore_search(&NavigationItem {
+            text: word.to_string(),
+            mode_id: Some("words".to_string()),
+            direction_id: Some(Direction::detect(word).id().to_string()),
+            selected: None,
+        });
+    }
+
+    /// Load the dictionaries on a worker thread. Until they are loaded, the search entry
+    /// is disabled, the progress is shown into the translation pane and the typed text
+    /// is queued into the search entry, see `queue_typed_text_event()`.
+    fn load_dictionaries(&self) {
+        self.loading.store(true, Ordering::SeqCst);
+        self.content.s_bar.search_entry.set_sensitive(false);
+        self.content
+            .inner_paned
+            .translation
+            .show_progress(0.0, "Loading dictionaries\u{2026}");
+
+        let (sender, receiver) = mpsc::channel();
+        let data_dir = self.data_dir.clone();
+        let encoding = self.encoding;
+        let stardict_paths = self.stardict_paths.clone();
+        thread::spawn(move || {
+            // The receiver is gone only if the window is closed meanwhile
+            let extra_files = stardict_paths.len();
+            let mut last_progress = LoadProgress::default();
+            let result = Dictionaries::load(data_dir.as_ref().map(|s| s.as_str()), encoding, &mut |load_progress| {
+                last_progress = LoadProgress {
+                    files_total: load_progress.files_total + extra_files,
+                    ..load_progress
+                };
+                let _ = sender.send(LoadMessage::Progress(last_progress));
+            });
+            let result = result.map(|mut dictionaries| {
+                for ifo_path in &stardict_paths {
+                    let entries_parsed = last_progress.entries_parsed;
+                    let added = StarDict::open(ifo_path).and_then(|stardict| {
+                        dictionaries.add_source(&stardict, &mut |entries| {
+                            let _ = sender.send(LoadMessage::Progress(LoadProgress {
+                                entries_parsed: entries_parsed + entries,
+                                ..last_progress
+                            }));
+                        })
+                    });
+                    match added {
+                        Ok(key) => {
+                            let entries = dictionaries.by_key(key).map_or(0, |dictionary| dictionary.len());
+                            last_progress.entries_parsed += entries;
+                        }
+                        Err(err) => {
+                            let _ = sender.send(LoadMessage::SourceError(err));
+                        }
+                    }
+                    last_progress.files_loaded += 1;
+                    let _ = sender.send(LoadMessage::Progress(last_progress));
+                }
+                // Built here, so that the first search does not block the window
+                let _ = sender.send(LoadMessage::Indexing);
+                dictionaries.build_search_indexes();
+                dictionaries
+            });
+            let _ = sender.send(LoadMessage::Done(result));
+        });
+
+        let app = self.clone();
+        gtk::timeout_add(LOAD_POLL_INTERVAL, move || {
+            let mut last_progress: Option<LoadProgress> = None;
+            loop {
+                match receiver.try_recv() {
+                    Ok(LoadMessage::Progress(load_progress)) => last_progress = Some(load_progress),
+                    Ok(LoadMessage::Indexing) => {
+                        last_progress = None;
+                        app.content
+                            .inner_paned
+                            .translation
+                            .show_progress(1.0, "Indexing the dictionaries for the search\u{2026}");
+                    }
+                    Ok(LoadMessage::SourceError(err)) => {
+                        error!("Can not load dictionary: {}", err);
+                        app.error_dialog("Unable to load dictionaries", err.to_string());
+                    }
+                    Ok(LoadMessage::Done(Ok(dictionaries))) => {
+                        let names: Vec<String> = dictionaries
+                            .extra
+                            .iter()
+                            .map(|dictionary| dictionary.name.clone())
+                            .collect();
+                        *app.dictionaries.lock().unwrap() = dictionaries;
+                        app.content.s_bar.add_dictionaries(&names);
+                        app.finish_loading();
+                        return Continue(false);
+                    }
+                    Ok(LoadMessage::Done(Err(err))) => {
+                        error!("Can not load dictionaries: {}", err);
+                        app.error_dialog(
+                            "Unable to load dictionaries",
+                            format!("{}\n\n{}", err, err.install_hint()),
+                        );
+                        app.finish_loading();
+                        return Continue(false);
+                    }
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        error!("The thread loading the dictionaries stopped unexpectedly");
+                        app.finish_loading();
+                        return Continue(false);
+                    }
+                }
+            }
+            if let Some(load_progress) = last_progress {
+                trace!("load_dictionaries: {:?}", load_progress);
+                app.content.inner_paned.translation.show_progress(
+                    load_progress.files_loaded as f64 / load_progress.files_total as f64,
+                    &format!(
+                        "Loading dictionaries: {} of {} files, {} entries",
+                        load_progress.files_loaded,
+                        load_progress.files_total,
+                        load_progress.entries_parsed
+                    ),
+                );
+            }
+            Continue(true)
+        });
+    }
+
+    /// Enable the search entry and search for the text typed while loading
+    fn finish_loading(&self) {
+        self.loading.store(false, Ordering::SeqCst);
+        self.content.inner_paned.translation.hide_progress();
+        let search_entry = &self.content.s_bar.search_entry;
+        search_entry.set_sensitive(true);
+        search_entry.grab_focus();
+        search_entry.set_position(-1);
+        debug!("finish_loading: queued text {:?}", search_entry.get_text());
+        self.repeat_search();
+    }
+
+    /// While the dictionaries are loaded, append the typed characters to the disabled
+    /// search entry, so the search runs once loading completes.
+    fn queue_typed_text_event(&self) {
+        let search_entry = self.content.s_bar.search_entry.clone();
+        let loading = self.loading.clone();
+        self.window.connect_key_press_event(move |_, gdk| {
+            if !loading.load(Ordering::SeqCst)
+                || gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK)
+            {
+                return Inhibit(false);
+            }
+            let mut queued_text = search_entry.get_text().unwrap_or_default();
+            match gdk.get_keyval() {
+                key::BackSpace => {
+                    queued_text.pop();
+                }
+                keyval => match gdk::keyval_to_unicode(keyval) {
+                    Some(c) if !c.is_control() => queued_text.push(c),
+                    _ => return Inhibit(false),
+                },
+            }
+            trace!("queue_typed_text_event: {:?}", queued_text);
+            search_entry.set_text(&queued_text);
+            Inhibit(true)
+        });
+    }
+
+    /// Shows an error dialog titled `title` with `message`, e.g. for dictionaries
+    /// which can not be loaded, as soon as the main window is displayed.
+    fn error_dialog(&self, title: &'static str, message: String) {
+        let window = self.window.clone();
+        gtk::idle_add(move || {
+            let dialog = MessageDialog::new(
+                Some(&window),
+                DialogFlags::MODAL,
+                MessageType::Error,
+                ButtonsType::Close,
+                &message,
+            );
+            dialog.set_title(&format!("RBEdic: {}", title));
+            dialog.run();
+            dialog.destroy();
+            Continue(false)
+        });
+    }
+
     /// Handles special functions that should be invoked when certain keys and key combinations
     /// are pressed on the keyboard.
     fn key_events(
@@ -221,6 +1140,7 @@ impl App {
         let add_2_history_button = self.content.s_bar.add_2_history.clone();
         let history_button = self.content.s_bar.history.clone();
         let history_dictdb_clonned = self.history_dictdb.clone();
+        let app = self.clone();
         // Each key press will invoke this function.
         self.window.connect_key_press_event(move |window, gdk| {
             match gdk.get_keyval() {
@@ -248,6 +1168,34 @@ impl App {
                     trace!("Pressed CTRL+s");
                     add_2_history_button.clicked();
                 }
+                // Review the history words when ctrl+r is pressed.
+                key if key == 'r' as u32 && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) => {
+                    trace!("Pressed CTRL+r");
+                    app.start_review();
+                }
+                // Delete the selected history word with the delete key into the words list.
+                key::Delete if app.content.inner_paned.words.tree_view.has_focus() => {
+                    trace!("Pressed Delete");
+                    app.delete_history_word();
+                }
+                // Restore the deleted history word when ctrl+z is pressed.
+                key if key == 'z' as u32 && gdk.get_state().contains(gdk::ModifierType::CONTROL_MASK) => {
+                    trace!("Pressed CTRL+z");
+                    if app.history_mode.load(Ordering::SeqCst) {
+                        app.undo_history_delete();
+                    }
+                }
+                // Go to the previous and to the next search with alt+left and alt+right.
+                key::Left if gdk.get_state().contains(gdk::ModifierType::MOD1_MASK) => {
+                    trace!("Pressed ALT+Left");
+                    app.go_back();
+                    return Inhibit(true);
+                }
+                key::Right if gdk.get_state().contains(gdk::ModifierType::MOD1_MASK) => {
+                    trace!("Pressed ALT+Right");
+                    app.go_forward();
+                    return Inhibit(true);
+                }
                 _ => (),
             }
             Inhibit(false)
@@ -289,8 +1237,12 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
 
 * See http://kbedic.sourceforge.net/questions.html (in Bulgarian)
 ";
+        let search_generation = self.search_generation.clone();
+        let history_mode = self.history_mode.clone();
         button_about.connect_clicked(move |_| {
             trace!("about_event: button About clicked");
+            search_generation.fetch_add(1, Ordering::SeqCst);
+            history_mode.store(false, Ordering::SeqCst);
             button_add_2_history.set_sensitive(false);
             tree_store.clear();
             right_buff.set_text(&about_text);
@@ -312,28 +1264,356 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
         let search_entry = content_clonned.s_bar.search_entry.clone();
         let button_history = content_clonned.s_bar.history.clone();
         button_history.connect_clicked(move |_| {
-            {
-                debug!("history_event: Clear searched_hash");
-                let mut searched_hash_locked = app_clonned.searched_hash.lock().unwrap();
-                searched_hash_locked.clear();
+            app_clonned.show_history();
+            search_entry.grab_focus();
+        });
+    }
+
+    /// Show the history words into the words list, the last added first
+    fn show_history(&self) {
+        self.search_generation.fetch_add(1, Ordering::SeqCst);
+        self.history_mode.store(true, Ordering::SeqCst);
+        {
+            debug!("show_history: Clear searched_hash");
+            let mut searched_hash_locked = self.searched_hash.lock().unwrap();
+            searched_hash_locked.clear();
+        }
+        {
+            let mut history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+            history_dictdb_unsorted.reverse();
+            trace!("show_history: history_data -> {:?}", history_dictdb_unsorted);
+            self.selection(&history_dictdb_unsorted, true, false);
+            if history_dictdb_unsorted.is_empty() {
+                self.content.inner_paned.translation.clear();
             }
-            {
-                let mut history_dictdb_unsorted =
-                    app_clonned.history_dictdb_unsorted.lock().unwrap();
-                history_dictdb_unsorted.reverse();
-                trace!(
-                    "history_event: history_data -> {:?}",
-                    history_dictdb_unsorted
-                );
-                app_clonned.selection(&history_dictdb_unsorted, true, false);
+        }
+        {
+            let mut history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+            history_dictdb_unsorted.reverse();
+        }
+    }
+
+    /// Program Review button, and show the number of words due today
+    fn review_event(&self) {
+        let app = self.clone();
+        self.content
+            .s_bar
+            .review
+            .connect_clicked(move |_| app.start_review());
+        self.update_due_count();
+    }
+
+    /// Show the number of history words due for review today on the History button.
+    /// The quizzes are enabled when there are history words.
+    fn update_due_count(&self) {
+        let due_count = match *self.deck.lock().unwrap() {
+            Some(ref deck) => {
+                let history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+                deck.due_count(&history_dictdb_unsorted, review::today())
             }
-            {
-                let mut history_dictdb_unsorted =
-                    app_clonned.history_dictdb_unsorted.lock().unwrap();
-                history_dictdb_unsorted.reverse();
+            None => 0,
+        };
+        debug!("update_due_count: {}", due_count);
+        let s_bar = &self.content.s_bar;
+        if due_count > 0 {
+            s_bar.history.set_label(&format!("_History ({})", due_count));
+        } else {
+            s_bar.history.set_label("_History");
+        }
+        s_bar.review.set_sensitive(due_count > 0);
+        s_bar
+            .quiz
+            .set_sensitive(!self.history_dictdb_unsorted.lock().unwrap().is_empty());
+    }
+
+    /// Review the history words due today with flashcards, see `ReviewDialog`
+    fn start_review(&self) {
+        let due = match *self.deck.lock().unwrap() {
+            Some(ref deck) => {
+                let history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+                deck.due(&history_dictdb_unsorted, review::today())
+            }
+            None => return,
+        };
+        debug!("start_review: {} words due", due.len());
+        if due.is_empty() {
+            return;
+        }
+        let app = self.clone();
+        let on_answer: Rc<dyn Fn(&DictDB, Grade)> = Rc::new(move |word, grade| {
+            let saved = match *app.deck.lock().unwrap() {
+                Some(ref mut deck) => deck.answer(word, grade, review::today()),
+                None => Ok(()),
+            };
+            if let Err(err) = saved {
+                error!("Can not write review file of {:?}: {}", app.history_file.path(), err);
+                app.error_dialog("Unable to save the review", err.to_string());
+            }
+            app.update_due_count();
+        });
+        ReviewDialog::new(&self.window, due, on_answer).show();
+    }
+
+    /// Program the items of the Quiz menu
+    fn quiz_event(&self) {
+        for &(mode, ref item) in &self.content.s_bar.quiz_items {
+            let app = self.clone();
+            item.connect_activate(move |_| app.start_quiz(mode));
+        }
+    }
+
+    /// Ask questions about the history words, see `QuizDialog`.
+    /// The answers are counted into the review file.
+    fn start_quiz(&self, mode: QuizMode) {
+        // The wrong choices come from the dictionaries
+        if mode == QuizMode::MultipleChoice && self.loading.load(Ordering::SeqCst) {
+            debug!("start_quiz: The dictionaries are loading");
+            return;
+        }
+        let questions = {
+            let history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+            let dictionaries = self.dictionaries.lock().unwrap();
+            quiz::questions(&history_dictdb_unsorted, mode, &dictionaries, QUIZ_LENGTH)
+        };
+        debug!("start_quiz: {:?}, {} questions", mode, questions.len());
+        if questions.is_empty() {
+            self.error_dialog(
+                "Unable to start the quiz",
+                "None of the history words can be asked: they have no translation or too few other entries to choose from.".to_string(),
+            );
+            return;
+        }
+        let app = self.clone();
+        let on_answer: Rc<dyn Fn(&DictDB, bool)> = Rc::new(move |word, right| {
+            let saved = match *app.deck.lock().unwrap() {
+                Some(ref mut deck) => deck.record_quiz(word, right, review::today()),
+                None => Ok(()),
+            };
+            if let Err(err) = saved {
+                error!("Can not write review file of {:?}: {}", app.history_file.path(), err);
+                app.error_dialog("Unable to save the quiz answer", err.to_string());
+            }
+        });
+        QuizDialog::new(&self.window, mode, questions, on_answer).show();
+    }
+
+    /// Program the editing of the history words: the context menu of the words list,
+    /// see also the Delete key and CTRL+z into `key_events()`
+    fn history_edit_event(&self) {
+        let words = self.content.inner_paned.words.clone();
+        let app = self.clone();
+        words.tree_view.connect_button_press_event(move |tree_view, event| {
+            if event.get_button() != 3 || !app.history_mode.load(Ordering::SeqCst) {
+                return Inhibit(false);
+            }
+            // Select the clicked row, the menu acts on the selected one
+            let (x, y) = event.get_position();
+            if let Some((Some(path), _, _, _)) = tree_view.get_path_at_pos(x as i32, y as i32) {
+                tree_view.get_selection().select_path(&path);
+                tree_view.set_cursor(&path, None, false);
+            }
+            let words = &app.content.inner_paned.words;
+            let has_word = words.selected_word().is_some();
+            words.delete_item.set_sensitive(has_word);
+            words.note_item.set_sensitive(has_word);
+            words
+                .undo_item
+                .set_sensitive(!app.history_undo.lock().unwrap().is_empty());
+            words.history_menu.popup_easy(event.get_button(), event.get_time());
+            Inhibit(true)
+        });
+        let app = self.clone();
+        words.delete_item.connect_activate(move |_| app.delete_history_word());
+        let app = self.clone();
+        words.note_item.connect_activate(move |_| app.edit_history_note());
+        let app = self.clone();
+        words.undo_item.connect_activate(move |_| app.undo_history_delete());
+    }
+
+    /// The history word selected into the words list, in history mode only
+    fn selected_history_word(&self) -> Option<DictDB> {
+        if !self.history_mode.load(Ordering::SeqCst) {
+            return None;
+        }
+        // The same word may be listed for both directions
+        let (word, label) = self.content.inner_paned.words.selected_labeled_word()?;
+        let history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+        history_dictdb_unsorted
+            .iter()
+            .find(|dict_db| dict_db.word == word && dict_db.direction.label() == label)
+            .cloned()
+    }
+
+    /// Delete the selected history word from the history file and from the words list.
+    /// The word can be restored with `undo_history_delete()`.
+    fn delete_history_word(&self) {
+        let dict_db = match self.selected_history_word() {
+            Some(dict_db) => dict_db,
+            None => return,
+        };
+        debug!("delete_history_word: {:?}", dict_db.word);
+        let mut deleted: Option<(usize, HistoryRecord)> = None;
+        let updated = self.history_file.update(|records| {
+            if let Some(index) = records.iter().position(|record| record.is_of(&dict_db.word, dict_db.direction)) {
+                deleted = Some((index, records.remove(index)));
             }
-            search_entry.grab_focus();
         });
+        if let Err(err) = updated {
+            error!("Can not write history file {:?}: {}", self.history_file.path(), err);
+            self.error_dialog(
+                "Unable to delete the word",
+                format!("Can not write {}: {}", self.history_file.path().display(), err),
+            );
+            return;
+        }
+        // The row shown next, the one below the deleted one
+        let next_word: Option<DictDB>;
+        let list_index: usize;
+        {
+            let mut history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+            list_index = match history_position(&history_dictdb_unsorted, &dict_db.word, dict_db.direction) {
+                Some(index) => index,
+                None => return,
+            };
+            history_dictdb_unsorted.remove(list_index);
+            // The list is shown in reverse order
+            next_word = history_dictdb_unsorted.get(list_index.saturating_sub(1)).cloned();
+            let mut history_dictdb = self.history_dictdb.lock().unwrap();
+            if let Some(index) = history_position(&history_dictdb, &dict_db.word, dict_db.direction) {
+                history_dictdb.remove(index);
+            }
+        }
+        self.history_notes.lock().unwrap().remove(&(dict_db.word.clone(), dict_db.direction));
+        if let Some((file_index, record)) = deleted {
+            self.history_undo.lock().unwrap().push(DeletedWord {
+                record,
+                file_index,
+                list_index,
+            });
+        }
+        self.show_history();
+        self.update_due_count();
+        if let Some(next) = next_word {
+            self.content
+                .inner_paned
+                .words
+                .select_labeled_word(&next.word, next.direction.label());
+        }
+    }
+
+    /// Restore the history word deleted last
+    fn undo_history_delete(&self) {
+        let deleted = match self.history_undo.lock().unwrap().pop() {
+            Some(deleted) => deleted,
+            None => return,
+        };
+        debug!("undo_history_delete: {:?}", deleted.record.headword);
+        let updated = self.history_file.update(|records| {
+            let index = cmp::min(deleted.file_index, records.len());
+            records.insert(index, deleted.record.clone());
+        });
+        if let Err(err) = updated {
+            error!("Can not write history file {:?}: {}", self.history_file.path(), err);
+            self.error_dialog(
+                "Unable to restore the word",
+                format!("Can not write {}: {}", self.history_file.path().display(), err),
+            );
+            self.history_undo.lock().unwrap().push(deleted);
+            return;
+        }
+        let dict_db = deleted.record.to_dict_db();
+        {
+            let mut history_dictdb_unsorted = self.history_dictdb_unsorted.lock().unwrap();
+            let index = cmp::min(deleted.list_index, history_dictdb_unsorted.len());
+            history_dictdb_unsorted.insert(index, dict_db.clone());
+            let mut history_dictdb = self.history_dictdb.lock().unwrap();
+            history_dictdb.push(dict_db);
+            history_dictdb.sort();
+        }
+        if !deleted.record.note.is_empty() {
+            self.history_notes
+                .lock()
+                .unwrap()
+                .insert((deleted.record.headword.clone(), deleted.record.direction), deleted.record.note.clone());
+        }
+        self.show_history();
+        self.update_due_count();
+        self.content
+            .inner_paned
+            .words
+            .select_labeled_word(&deleted.record.headword, deleted.record.direction.label());
+    }
+
+    /// Edit the personal note of the selected history word
+    fn edit_history_note(&self) {
+        let dict_db = match self.selected_history_word() {
+            Some(dict_db) => dict_db,
+            None => return,
+        };
+        let key = (dict_db.word.clone(), dict_db.direction);
+        let note = self.history_notes.lock().unwrap().get(&key).cloned().unwrap_or_default();
+        let note = match self.note_dialog(&dict_db.word, &note) {
+            Some(note) => note.trim().to_string(),
+            None => return,
+        };
+        debug!("edit_history_note: {:?} -> {:?}", dict_db.word, note);
+        let updated = self.history_file.update(|records| {
+            for record in records.iter_mut() {
+                if record.is_of(&dict_db.word, dict_db.direction) {
+                    record.note = note.clone();
+                }
+            }
+        });
+        if let Err(err) = updated {
+            error!("Can not write history file {:?}: {}", self.history_file.path(), err);
+            self.error_dialog(
+                "Unable to save the note",
+                format!("Can not write {}: {}", self.history_file.path().display(), err),
+            );
+            return;
+        }
+        {
+            let mut history_notes = self.history_notes.lock().unwrap();
+            if note.is_empty() {
+                history_notes.remove(&key);
+            } else {
+                history_notes.insert(key, note);
+            }
+        }
+        self.show_entry(&dict_db);
+    }
+
+    /// Ask for the note of `word`, starting with `note`. Returns `None` if cancelled.
+    fn note_dialog(&self, word: &str, note: &str) -> Option<String> {
+        let dialog = Dialog::new_with_buttons(
+            Some(&format!("RBEdic: Note for {}", word)),
+            Some(&self.window),
+            DialogFlags::MODAL | DialogFlags::DESTROY_WITH_PARENT,
+            &[
+                ("_Cancel", ResponseType::Cancel.into()),
+                ("_Save", ResponseType::Accept.into()),
+            ],
+        );
+        dialog.set_default_size(360, 160);
+        let buff = TextBuffer::new(None);
+        buff.set_text(note);
+        let text_view = TextView::new_with_buffer(&buff);
+        text_view.set_wrap_mode(WrapMode::Word);
+        let scrolled = ScrolledWindow::new(None, None);
+        scrolled.add(&text_view);
+        scrolled.set_vexpand(true);
+        dialog.get_content_area().add(&scrolled);
+        dialog.show_all();
+        text_view.grab_focus();
+        let response = dialog.run();
+        let accept: i32 = ResponseType::Accept.into();
+        let note = buff.get_text(&buff.get_start_iter(), &buff.get_end_iter(), false);
+        dialog.destroy();
+        if response == accept {
+            note
+        } else {
+            None
+        }
     }
 
     /// Program add_2_history button
@@ -342,7 +1622,7 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
         history_dictdb: Rc<Mutex<Vec<DictDB>>>,
         history_dictdb_unsorted: Rc<Mutex<Vec<DictDB>>>,
     ) {
-        let right_buff = self.content.inner_paned.translation.buff.clone();
+        let translation = self.content.inner_paned.translation.clone();
         let left_selection = self.content.inner_paned.words.tree_view.get_selection();
         let button_history = &self.content.s_bar.history;
         let button_history_clonned = button_history.clone();
@@ -351,12 +1631,18 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
         let search_entry = &self.content.s_bar.search_entry;
         let search_entry_clonned = search_entry.clone();
         let selection_atomic_isize = self.selection_isize.clone();
+        let app = self.clone();
 
         &self.content.s_bar.add_2_history.connect_clicked(move |_| {
             // Get left tree_view selection value
             let left_selection_value: String = match left_selection.get_selected() {
                 Some((left_model, iter)) => {
                     match left_model.get_value(&iter, 0).get::<String>() {
+                        Some(ref value_string) if value_string.is_empty() => {
+                            trace!("add_2_history_event: Information row selected. Exit from this method.");
+                            button_add_2_history_clonned.set_sensitive(false);
+                            return;
+                        }
                         Some(value_string) => {
                             trace!("add_2_history_event: Selected -> {}", value_string);
                             //button_history_clonned.set_sensitive(true);
@@ -377,40 +1663,61 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                     return;
                 }
             };
+            // The shown entry, whose direction is recorded
+            let selected = app.searched_hash.lock().unwrap().get(&left_selection_value).cloned();
+            let direction = match selected {
+                Some(dict_db) => dict_db.direction,
+                None => {
+                    trace!("add_2_history_event: {:?} is not shown. Exit from this method.", left_selection_value);
+                    button_add_2_history_clonned.set_sensitive(false);
+                    return;
+                }
+            };
             // Insert into history_dictdb
             let mut history_dictdb = history_dictdb.lock().unwrap();
             let mut history_dictdb_unsorted = history_dictdb_unsorted.lock().unwrap();
             history_dictdb.sort();
-            let search_struct = DictDB {
-                word: left_selection_value.clone(),
-                translation: "__".to_string(),
-            };
             // Search for duplicates and set the button Add
-            match history_dictdb.binary_search(&search_struct) {
-                Ok(_index) => {
-                    trace!("add_2_history_event: bin search OK -> {}", _index);
+            match history_position(&history_dictdb, &left_selection_value, direction) {
+                Some(_index) => {
+                    trace!("add_2_history_event: already added -> {}", _index);
                     button_add_2_history_clonned.set_sensitive(false);
                 }
-                Err(_index_err) => {
-                    button_history_clonned.set_sensitive(true);
+                None => {
                     button_add_2_history_clonned.set_sensitive(false);
-                    let iter1: TextIter = right_buff.get_start_iter();
-                    let iter2: TextIter = right_buff.get_end_iter();
-                    let right_buff_text = right_buff.get_text(&iter1, &iter2, false).unwrap();
-                    // Print to standard out
-                    // Warning: these dashes are used for field delimiter in
-                    // database.rs::parse_history()
-                    println!("------------------------------------------------------------------------------");
-                    println!("{}", right_buff_text);
+                    // The entry as written into the dictionary, not as formatted
+                    let right_buff_text = translation.raw_text();
+                    let record = HistoryRecord::new(&right_buff_text, direction);
+                    if let Err(err) = app.history_file.append(&record) {
+                        error!("Can not write history file {:?}: {}", app.history_file.path(), err);
+                        // The word is not added, it can be tried again
+                        button_add_2_history_clonned.set_sensitive(true);
+                        drop(history_dictdb);
+                        drop(history_dictdb_unsorted);
+                        app.error_dialog(
+                            "Unable to save the word",
+                            format!("Can not write {}: {}", app.history_file.path().display(), err),
+                        );
+                        return;
+                    }
+                    button_history_clonned.set_sensitive(true);
+                    if app.echo_stdout {
+                        println!("{}", record.to_json_line());
+                    }
                     let dictdb_entry = DictDB {
                         word: left_selection_value.clone(),
                         translation: right_buff_text.to_string(),
+                        direction,
                     };
                     history_dictdb.push(dictdb_entry.clone());
                     history_dictdb.sort();
                     history_dictdb_unsorted.push(dictdb_entry.clone());
                     search_entry_clonned.grab_focus();
                     selection_atomic_isize.store(400, Ordering::SeqCst);
+                    // The new word is due for review at once
+                    drop(history_dictdb);
+                    drop(history_dictdb_unsorted);
+                    app.update_due_count();
                 }
             };
         });
@@ -425,7 +1732,7 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
         let content_clonned = content.clone();
 
         let left_tree = content_clonned.inner_paned.words.tree_view.clone();
-        let right_buff = content_clonned.inner_paned.translation.buff.clone();
+        let translation = content_clonned.inner_paned.translation.clone();
         let button_add_2_history = content_clonned.s_bar.add_2_history.clone();
         let button_history = content_clonned.s_bar.history.clone();
         // selection
@@ -466,6 +1773,12 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                 "selection: before for loop: searched_hash LEN == {}",
                 searched_hash_locked.len()
             );
+            // History items come from both bedic directions
+            let label = |dict_db: &DictDB| if history_mode {
+                dict_db.direction.label().to_string()
+            } else {
+                self.entry_label(dict_db)
+            };
             for i in vec_dict_db_loop {
                 // insert_with_values takes two slices: column indices and ToValue
                 // trait objects. ToValue is implemented for strings, numeric types,
@@ -473,24 +1786,25 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                 content.inner_paned.words.tree_store.insert_with_values(
                     None,
                     None,
-                    &[0],
-                    &[&format!("{}", &i.word)],
+                    &[0, 1],
+                    &[&format!("{}", &i.word), &label(i)],
                 );
                 //trace!("selection: for loop: Insert into searched_hash {} -> {}", i.clone().word, i.clone().translation);
-                searched_hash_locked.insert(i.clone().word, i.clone().translation);
+                searched_hash_locked.insert(i.word.clone(), i.clone());
                 // Set cursor on first item
                 if j == 1 {
                     // Selection
                     let path_default = TreePath::new_first();
                     left_selection_clonned.select_path(&path_default);
                     left_tree.set_cursor(&path_default, None, false);
-                    right_buff.set_text(&i.translation.as_str());
+                    self.show_entry(i);
                     // Search for duplicates into history data and set Add button sensitivity
                     // if history_mode == true -> history_data == vec_dict_db
                     // else  history_data = history_dictdb with mutex
                     let search_struct = DictDB {
                         word: i.clone().word,
                         translation: "___".to_string(),
+                        direction: i.direction,
                     };
                     if history_mode {
                         //let history_data = vec_dict_db;
@@ -518,12 +1832,12 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                             if history_dictdb.len() == 0 {
                                 button_history.set_sensitive(false);
                             }
-                            match history_dictdb.binary_search(&search_struct) {
-                                Ok(index) => {
+                            match history_position(&history_dictdb, &search_struct.word, search_struct.direction) {
+                                Some(index) => {
                                     trace!("selection: into loop Ok: {:?}", index);
                                     button_add_2_history.set_sensitive(false);
                                 }
-                                Err(_index_err) => {
+                                None => {
                                     trace!("selection: into loop Err for -> {:?}", search_struct);
                                     button_add_2_history.set_sensitive(true);
                                 }
@@ -572,6 +1886,10 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                                 selection_atomic_isize
                             );
                             match left_model.get_value(&iter, 0).get::<String>() {
+                                Some(ref value_string) if value_string.is_empty() => {
+                                    trace!("selection: connect_changed: Information row selected");
+                                    button_add_2_history.set_sensitive(false);
+                                }
                                 Some(value_string) => {
                                     let value_string_owned = value_string.to_owned();
                                     let value_str = value_string.as_str();
@@ -586,6 +1904,8 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                                     // Get hash value and write to right_buff
                                     let sai = selection_atomic_isize.load(Ordering::SeqCst);
                                     trace!("selection: connect_changed: searched_hash and value_str == -> {}, sai == {}", value_str, sai);
+                                    // Direction of the shown entry, the word may be into the history for the other one
+                                    let mut shown_direction: Option<Direction> = None;
                                     {
                                         match app2.searched_hash.try_lock() {
                                             Ok(searched_hash_locked_1) => {
@@ -594,12 +1914,13 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                                                     Some(s) => {
                                                         trace!("selection: connect_changed: Success: Searched hash key -> {}", value_str);
                                                         debug!("selection: connect_changed: searched_hash LEN == {}", searched_hash_locked_1.len());
-                                                        right_buff.set_text(&s);
+                                                        shown_direction = Some(s.direction);
+                                                        app.show_entry(s);
                                                     }
                                                     None => {
                                                         trace!("selection: connect_changed: Warn: Searched hash returned Option is None and value_str -> {}", value_str);
                                                         //right_buff.set_text(value_str);
-                                                        right_buff.set_text("");
+                                                        translation.clear();
                                                         {
                                                             let history_dictdb_locked =
                                                                 app.history_dictdb.lock().unwrap();
@@ -627,27 +1948,25 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
                                         trace!("selection: connect_changed: RETURN from method because selection_atomic_isize_2 < -99 -> {}", selection_atomic_isize_2);
                                         return;
                                     } else {
-                                        let search_struct = DictDB {
-                                            word: value_string_owned,
-                                            translation: "___".to_string(),
-                                        };
                                         if history_mode == false {
                                             trace!("selection: connect_changed: history_mode {} and ATOMICISIZE BEFORE binary_search -> {:?}", history_mode, selection_atomic_isize);
                                             {
                                                 let history_dictdb =
                                                     app.history_dictdb.lock().unwrap();
                                                 //history_dictdb.sort();
-                                                match history_dictdb.binary_search(&search_struct) {
-                                                    Ok(_index) => {
+                                                let position = history_dictdb.iter().position(|dict_db| {
+                                                    dict_db.word == value_string_owned
+                                                        && shown_direction.is_none_or(|direction| dict_db.direction == direction)
+                                                });
+                                                match position {
+                                                    Some(_index) => {
                                                         trace!("selection: connect_changed: bin_search Ok: {:?}", history_dictdb[_index]);
                                                         // Workaround
-                                                        right_buff.set_text(
-                                                            &history_dictdb[_index].translation,
-                                                        );
+                                                        app.show_entry(&history_dictdb[_index]);
                                                         button_add_2_history.set_sensitive(false);
                                                     }
-                                                    Err(_index_err) => {
-                                                        trace!("selection: connect_changed: bin_search Err for -> {:?}", search_struct);
+                                                    None => {
+                                                        trace!("selection: connect_changed: bin_search Err for -> {:?}", value_string_owned);
                                                         button_add_2_history.set_sensitive(true);
                                                         selection_atomic_isize
                                                             .store(-100, Ordering::SeqCst);
@@ -680,3 +1999,11 @@ https://www.gnu.org/licenses/old-licenses/gpl-2.0.en.html
         }
     }
 }
+
+/// Position of `word` of `direction` into the history words `history`. The same word
+/// may be recorded for both directions, while the entries compare by word only.
+fn history_position(history: &[DictDB], word: &str, direction: Direction) -> Option<usize> {
+    history
+        .iter()
+        .position(|dict_db| dict_db.word == word && dict_db.direction == direction)
+}
@@ -6,10 +6,18 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use gtk::{Box, Button, CellLayoutExt, CellRendererText, ContainerExt, EntryExt, Orientation,
-          Paned, PanedExt, ScrolledWindow, SearchEntry, StaticType, StyleContextExt, TextBuffer,
-          TextView, TextViewExt, TreeStore, TreeView, TreeViewColumn, TreeViewExt, WidgetExt,
-          WrapMode};
+use gtk::{Box, Button, ButtonExt, CellLayoutExt, CellRendererText, ComboBoxExt, ComboBoxText,
+          ComboBoxTextExt, ContainerExt, EntryExt, Menu, MenuButton, MenuButtonExt, MenuItem, MenuShellExt, Orientation, Paned, PanedExt, ProgressBar,
+          ProgressBarExt, ReliefStyle,
+          ScrolledWindow, SearchEntry, StaticType, StyleContextExt, TextBuffer, TextBufferExt, TextView, TextViewExt, ToValue,
+          TreeModelExt, TreeSelectionExt, TreeStore, TreeStoreExt, TreeStoreExtManual, TreeView, TreeViewColumn,
+          TreeViewExt, WidgetExt, WrapMode};
+use rbedic::Direction;
+use rbedic::quiz::{QuizMode, QUIZ_MODES};
+use std::rc::Rc;
+use std::sync::Mutex;
+
+use super::render;
 
 #[derive(Clone)]
 pub struct Content {
@@ -18,11 +26,32 @@ pub struct Content {
     pub inner_paned: InnerPaned,
 }
 
+/// Prefix of the ids of the extra dictionaries into the direction selector
+const EXTRA_DICTIONARY_ID: &str = "dict:";
+
+/// What the searched text is matched against
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SearchMode {
+    /// Words starting with the searched text
+    Words,
+    /// Translations containing the searched text
+    FullText,
+    /// Words matching a wildcard pattern or a regular expression
+    Pattern,
+}
+
 #[derive(Clone)]
 pub struct SBar {
     pub container: Box,
     pub search_entry: SearchEntry,
+    pub mode: ComboBoxText,
+    pub direction: ComboBoxText,
     pub history: Button,
+    /// Flashcards of the history words due today
+    pub review: Button,
+    /// Quizzes of the history words, one menu item for each mode
+    pub quiz: MenuButton,
+    pub quiz_items: Vec<(QuizMode, MenuItem)>,
     pub add_2_history: Button,
 }
 
@@ -38,13 +67,22 @@ pub struct Words {
     pub container: ScrolledWindow,
     pub tree_view: TreeView,
     pub tree_store: TreeStore,
+    /// Context menu of the history words
+    pub history_menu: Menu,
+    pub delete_item: MenuItem,
+    pub note_item: MenuItem,
+    pub undo_item: MenuItem,
 }
 
 #[derive(Clone)]
 pub struct Translation {
-    pub container: ScrolledWindow,
+    pub container: Box,
+    pub progress: ProgressBar,
+    pub suggestion: Button,
     pub text_view: TextView,
     pub buff: TextBuffer,
+    /// Text of the shown entry, as written into the dictionary
+    pub raw: Rc<Mutex<String>>,
 }
 
 impl Content {
@@ -71,8 +109,41 @@ impl SBar {
         // Create The Sbar
         let container = Box::new(Orientation::Horizontal, 0);
         let search_entry = SearchEntry::new();
+        let mode = ComboBoxText::new();
+        mode.append(Some("words"), "Words");
+        mode.append(Some("fulltext"), "Full text");
+        mode.append(Some("pattern"), "Pattern");
+        mode.set_active_id(Some("words"));
+        mode.set_tooltip_text(
+            "Search for words, for text into translations,\n\
+             or for words matching a pattern: *tion, b?ok, ^.a.e$",
+        );
+        let direction = ComboBoxText::new();
+        direction.append(Some("auto"), "Auto");
+        direction.append(Some(Direction::EnBg.id()), Direction::EnBg.label());
+        direction.append(Some(Direction::BgEn.id()), Direction::BgEn.label());
+        direction.set_active_id(Some("auto"));
+        direction.set_tooltip_text("Translation direction. Auto detects it by the alphabet of the searched word");
         let history = Button::new_with_mnemonic("_History");
-        history.set_tooltip_text("View history. CTRL+d");
+        history.set_tooltip_text("View history. CTRL+d\nThe number of words due for review today is shown in brackets");
+        let review = Button::new_with_mnemonic("_Review");
+        review.set_tooltip_text("Review the history words due today with flashcards. CTRL+r");
+        review.set_sensitive(false);
+        let quiz = MenuButton::new();
+        quiz.set_label("_Quiz");
+        quiz.set_use_underline(true);
+        quiz.set_tooltip_text("Quiz on the history words: choose the translation, or type it");
+        quiz.set_sensitive(false);
+        let quiz_menu = Menu::new();
+        let quiz_items: Vec<(QuizMode, MenuItem)> = QUIZ_MODES
+            .iter()
+            .map(|&mode| (mode, MenuItem::new_with_label(mode.label())))
+            .collect();
+        for &(_, ref item) in &quiz_items {
+            quiz_menu.append(item);
+        }
+        quiz_menu.show_all();
+        quiz.set_popup(Some(&quiz_menu));
         let add_2_history = Button::new_with_mnemonic("_Add");
         add_2_history
             .get_style_context()
@@ -83,16 +154,60 @@ impl SBar {
         search_entry.set_hexpand(true);
         search_entry.set_max_length(50);
         container.add(&search_entry);
+        container.add(&mode);
+        container.add(&direction);
         container.add(&history);
+        container.add(&review);
+        container.add(&quiz);
         container.add(&add_2_history);
 
         SBar {
             container,
             search_entry,
+            mode,
+            direction,
             history,
+            review,
+            quiz,
+            quiz_items,
             add_2_history,
         }
     }
+
+    /// Selected search mode
+    pub fn mode(&self) -> SearchMode {
+        match self.mode.get_active_id() {
+            Some(ref id) if id == "fulltext" => SearchMode::FullText,
+            Some(ref id) if id == "pattern" => SearchMode::Pattern,
+            _ => SearchMode::Words,
+        }
+    }
+
+    /// Selected translation direction, `None` for auto detection
+    pub fn direction(&self) -> Option<Direction> {
+        self.direction
+            .get_active_id()
+            .and_then(|id| id.parse::<Direction>().ok())
+    }
+
+    /// Add the names of the extra (e.g. StarDict) dictionaries to the direction selector
+    pub fn add_dictionaries(&self, names: &[String]) {
+        for (index, name) in names.iter().enumerate() {
+            let id = format!("{}{}", EXTRA_DICTIONARY_ID, index);
+            self.direction.append(Some(id.as_str()), name);
+        }
+    }
+
+    /// Index of the selected extra dictionary, `None` for the bedic ones
+    pub fn extra_dictionary(&self) -> Option<usize> {
+        self.direction.get_active_id().and_then(|id| {
+            if id.starts_with(EXTRA_DICTIONARY_ID) {
+                id[EXTRA_DICTIONARY_ID.len()..].parse().ok()
+            } else {
+                None
+            }
+        })
+    }
 }
 
 impl InnerPaned {
@@ -122,18 +237,113 @@ impl Words {
     pub fn new() -> Words {
         // Create TreeView on the left pane
         let tree_view = TreeView::new();
-        let tree_store = TreeStore::new(&[String::static_type()]);
+        // Columns: word, direction label, info (e.g. marker of fuzzy matches)
+        let tree_store = TreeStore::new(&[
+            String::static_type(),
+            String::static_type(),
+            String::static_type(),
+        ]);
         let container = ScrolledWindow::new(None, None);
 
         tree_view.set_model(Some(&tree_store));
         tree_view.set_headers_visible(false);
-        append_text_column(&tree_view);
+        append_text_column(&tree_view, 0);
+        append_text_column(&tree_view, 1);
+        append_text_column(&tree_view, 2);
         container.add(&tree_view);
 
+        let history_menu = Menu::new();
+        let delete_item = MenuItem::new_with_mnemonic("_Delete");
+        let note_item = MenuItem::new_with_mnemonic("Edit _note\u{2026}");
+        let undo_item = MenuItem::new_with_mnemonic("_Undo delete");
+        history_menu.append(&delete_item);
+        history_menu.append(&note_item);
+        history_menu.append(&undo_item);
+        history_menu.show_all();
+
         Words {
             container,
             tree_view,
             tree_store,
+            history_menu,
+            delete_item,
+            note_item,
+            undo_item,
+        }
+    }
+
+    /// Append a row without word, showing `info` only
+    pub fn append_info_row(&self, info: &str) {
+        self.tree_store
+            .insert_with_values(None, None, &[0, 1, 2], &[&"", &"", &info]);
+    }
+
+    pub fn remove_last_row(&self) {
+        let rows = self.tree_store.iter_n_children(None);
+        if rows > 0 {
+            if let Some(iter) = self.tree_store.iter_nth_child(None, rows - 1) {
+                self.tree_store.remove(&iter);
+            }
+        }
+    }
+
+    /// Word of the selected row, `None` if no word is selected
+    pub fn selected_word(&self) -> Option<String> {
+        let (model, iter) = self.tree_view.get_selection().get_selected()?;
+        model
+            .get_value(&iter, 0)
+            .get::<String>()
+            .filter(|word| !word.is_empty())
+    }
+
+    /// Word and label of the selected row, `None` if no word is selected
+    pub fn selected_labeled_word(&self) -> Option<(String, String)> {
+        let (model, iter) = self.tree_view.get_selection().get_selected()?;
+        let word = model.get_value(&iter, 0).get::<String>().filter(|word| !word.is_empty())?;
+        let label = model.get_value(&iter, 1).get::<String>().unwrap_or_default();
+        Some((word, label))
+    }
+
+    /// Select the row of `word`. Returns false if `word` is not listed.
+    pub fn select_word(&self, word: &str) -> bool {
+        self.select_row(word, None)
+    }
+
+    /// Select the row of `word` with `label`, e.g. a history word of one direction.
+    /// Returns false if there is no such row.
+    pub fn select_labeled_word(&self, word: &str, label: &str) -> bool {
+        self.select_row(word, Some(label))
+    }
+
+    fn select_row(&self, word: &str, label: Option<&str>) -> bool {
+        let iter = match self.tree_store.get_iter_first() {
+            Some(iter) => iter,
+            None => return false,
+        };
+        loop {
+            let value = |column| self.tree_store.get_value(&iter, column).get::<String>();
+            if value(0).as_deref() == Some(word) && label.is_none_or(|label| value(1).as_deref() == Some(label)) {
+                if let Some(path) = self.tree_store.get_path(&iter) {
+                    self.tree_view.get_selection().select_path(&path);
+                    self.tree_view.set_cursor(&path, None, false);
+                    return true;
+                }
+            }
+            if !self.tree_store.iter_next(&iter) {
+                return false;
+            }
+        }
+    }
+
+    /// Write `infos` into the info column, one per row from row `first_row`
+    pub fn set_info(&self, first_row: usize, infos: &[String]) {
+        if let Some(iter) = self.tree_store.iter_nth_child(None, first_row as i32) {
+            for info in infos {
+                self.tree_store.set_value(&iter, 2, &info.to_value());
+                if !self.tree_store.iter_next(&iter) {
+                    break;
+                }
+            }
         }
     }
 }
@@ -142,28 +352,101 @@ impl Translation {
     pub fn new() -> Translation {
         // Create TextView on the right pane
         let buff = TextBuffer::new(None);
+        render::create_tags(&buff);
         let text_view = TextView::new_with_buffer(&buff);
         text_view.set_editable(false);
         text_view.set_wrap_mode(WrapMode::Word);
         text_view.set_right_margin(10);
         text_view.set_left_margin(10);
 
-        let container = ScrolledWindow::new(None, None);
-        container.add(&text_view);
+        let scrolled = ScrolledWindow::new(None, None);
+        scrolled.add(&text_view);
+        scrolled.set_vexpand(true);
+
+        // "Did you mean ..." banner, hidden until there is a suggestion
+        let suggestion = Button::new_with_label("");
+        suggestion.set_relief(ReliefStyle::None);
+        suggestion.set_no_show_all(true);
+        suggestion.set_tooltip_text("Search for the word retyped with another keyboard layout");
+
+        // Progress of loading the dictionaries, hidden when they are loaded
+        let progress = ProgressBar::new();
+        progress.set_show_text(true);
+        progress.set_no_show_all(true);
+
+        let container = Box::new(Orientation::Vertical, 0);
+        container.add(&progress);
+        container.add(&suggestion);
+        container.add(&scrolled);
 
         Translation {
             container,
+            progress,
+            suggestion,
             text_view,
             buff,
+            raw: Rc::new(Mutex::new(String::new())),
+        }
+    }
+
+    /// Show the entry `translation`, formatted, see `render::render_entry()`,
+    /// followed by the personal `note` of the user
+    pub fn show_entry(&self, translation: &str, note: Option<&str>, is_headword: &dyn Fn(&str) -> bool) {
+        *self.raw.lock().unwrap() = translation.to_string();
+        render::render_entry(&self.buff, translation, is_headword);
+        if let Some(note) = note {
+            render::render_note(&self.buff, note);
         }
     }
+
+    /// The word of the link at the cursor, which is moved to the clicked text
+    pub fn link_at_cursor(&self) -> Option<String> {
+        // A selection is made by dragging, not a click
+        if self.buff.get_has_selection() {
+            return None;
+        }
+        let cursor = self.buff.get_insert()?;
+        render::link_at(&self.buff, &self.buff.get_iter_at_mark(&cursor))
+    }
+
+    pub fn clear(&self) {
+        self.raw.lock().unwrap().clear();
+        self.buff.set_text("");
+    }
+
+    /// Text of the shown entry, without formatting
+    pub fn raw_text(&self) -> String {
+        self.raw.lock().unwrap().clone()
+    }
+
+    /// Show the "Did you mean ..." banner for `word`
+    pub fn show_suggestion(&self, word: &str) {
+        self.suggestion
+            .set_label(&format!("Did you mean \u{201C}{}\u{201D}?", word));
+        self.suggestion.show();
+    }
+
+    pub fn hide_suggestion(&self) {
+        self.suggestion.hide();
+    }
+
+    /// Show the progress bar filled to `fraction` (0.0 to 1.0) with `text`
+    pub fn show_progress(&self, fraction: f64, text: &str) {
+        self.progress.set_fraction(fraction);
+        self.progress.set_text(Some(text));
+        self.progress.show();
+    }
+
+    pub fn hide_progress(&self) {
+        self.progress.hide();
+    }
 }
 
-fn append_text_column(tree: &TreeView) {
+fn append_text_column(tree: &TreeView, column_index: i32) {
     let column = TreeViewColumn::new();
     let cell = CellRendererText::new();
 
     column.pack_start(&cell, true);
-    column.add_attribute(&cell, "text", 0);
+    column.add_attribute(&cell, "text", column_index);
     tree.append_column(&column);
 }
@@ -6,12 +6,16 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use gtk::{Button, HeaderBar, HeaderBarExt, WidgetExt};
+use gtk::{Box, Button, ContainerExt, HeaderBar, HeaderBarExt, IconSize, Orientation, StyleContextExt,
+          WidgetExt};
 
 #[derive(Clone)]
 pub struct Header {
     pub container: HeaderBar,
     pub about: Button,
+    /// Return to the previous and to the next search of the session
+    pub back: Button,
+    pub forward: Button,
 }
 
 impl Header {
@@ -22,13 +26,33 @@ impl Header {
 
         about.set_tooltip_text("About RBEdic.");
 
+        let back = Button::new_from_icon_name("go-previous-symbolic", IconSize::Button.into());
+        back.set_tooltip_text("Previous search. Alt+Left");
+        back.set_sensitive(false);
+        let forward = Button::new_from_icon_name("go-next-symbolic", IconSize::Button.into());
+        forward.set_tooltip_text("Next search. Alt+Right");
+        forward.set_sensitive(false);
+        // Join the buttons, like the ones of a web browser
+        let navigation = Box::new(Orientation::Horizontal, 0);
+        navigation
+            .get_style_context()
+            .map(|x| x.add_class("linked"));
+        navigation.add(&back);
+        navigation.add(&forward);
+
         // Sets the text to display in the title section of the header bar.
         container.set_title("RBEdic");
         // Enable the window controls within this headerbar.
         container.set_show_close_button(true);
+        container.pack_start(&navigation);
         container.pack_start(&about);
 
         // Returns the header and all of it's state
-        Header { container, about }
+        Header {
+            container,
+            about,
+            back,
+            forward,
+        }
     }
 }
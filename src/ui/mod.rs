@@ -9,7 +9,14 @@
 mod app;
 mod header;
 mod content;
+mod navigation;
+mod quiz;
+mod render;
+mod review;
 
 pub use self::app::App;
 pub use self::header::Header;
-pub use self::content::Content;
+pub use self::content::{Content, SearchMode};
+pub use self::navigation::{Navigation, NavigationItem};
+pub use self::quiz::QuizDialog;
+pub use self::review::ReviewDialog;
@@ -0,0 +1,77 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+/// A search which can be returned to: the searched text with the selected
+/// mode and direction, and the word selected into the results
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NavigationItem {
+    pub text: String,
+    pub mode_id: Option<String>,
+    pub direction_id: Option<String>,
+    /// Selected row of the words list, the first one if `None`
+    pub selected: Option<String>,
+}
+
+impl NavigationItem {
+    /// Return true if `other` is the same search, whatever row is selected
+    fn same_search(&self, other: &NavigationItem) -> bool {
+        self.text == other.text && self.mode_id == other.mode_id && self.direction_id == other.direction_id
+    }
+}
+
+/// Back and forward stacks of the searches of the session
+#[derive(Clone, Debug, Default)]
+pub struct Navigation {
+    back: Vec<NavigationItem>,
+    forward: Vec<NavigationItem>,
+}
+
+impl Navigation {
+    /// Remember `current` before leaving it for another search.
+    /// The searches which could be gone forward to are dropped.
+    pub fn push(&mut self, current: NavigationItem) {
+        if self.back.last().map_or(false, |last| last.same_search(&current)) {
+            // Keep the row selected last
+            self.back.pop();
+        }
+        self.back.push(current);
+        self.forward.clear();
+    }
+
+    /// The previous search, `current` can be gone forward to afterwards
+    pub fn back(&mut self, current: NavigationItem) -> Option<NavigationItem> {
+        let previous = pop_other(&mut self.back, &current)?;
+        self.forward.push(current);
+        Some(previous)
+    }
+
+    /// The next search, `current` can be gone back to afterwards
+    pub fn forward(&mut self, current: NavigationItem) -> Option<NavigationItem> {
+        let next = pop_other(&mut self.forward, &current)?;
+        self.back.push(current);
+        Some(next)
+    }
+
+    pub fn can_go_back(&self) -> bool {
+        !self.back.is_empty()
+    }
+
+    pub fn can_go_forward(&self) -> bool {
+        !self.forward.is_empty()
+    }
+}
+
+/// Pop the last item of `stack` which is not the same search as `current`
+fn pop_other(stack: &mut Vec<NavigationItem>, current: &NavigationItem) -> Option<NavigationItem> {
+    while let Some(item) = stack.pop() {
+        if !item.same_search(current) {
+            return Some(item);
+        }
+    }
+    None
+}
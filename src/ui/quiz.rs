@@ -0,0 +1,259 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Quizzes of the history words, see `rbedic::quiz`
+
+use gdk;
+use gtk::*;
+use rbedic::quiz::{Question, QuizMode, CHOICES};
+use rbedic::DictDB;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Dialog asking the questions of a quiz one after the other, and showing the
+/// score of the session at the end
+#[derive(Clone)]
+pub struct QuizDialog {
+    pub dialog: Dialog,
+    progress: Label,
+    prompt: Label,
+    /// Buttons of the multiple-choice questions
+    choices: Box,
+    /// Typed answer, with its Check button
+    answer_box: Box,
+    answer: Entry,
+    check: Button,
+    feedback: Label,
+    next: Button,
+    questions: Rc<Vec<Question>>,
+    /// Index of the current question into `questions`
+    current: Rc<AtomicUsize>,
+    right_answers: Rc<AtomicUsize>,
+    /// True when the current question is answered
+    answered: Rc<AtomicBool>,
+    on_answer: Rc<dyn Fn(&DictDB, bool)>,
+}
+
+impl QuizDialog {
+    /// A dialog asking `questions`. `on_answer` records whether each answer is right.
+    pub fn new(
+        parent: &Window,
+        mode: QuizMode,
+        questions: Vec<Question>,
+        on_answer: Rc<dyn Fn(&DictDB, bool)>,
+    ) -> QuizDialog {
+        let dialog = Dialog::new();
+        dialog.set_title(&format!("RBEdic: Quiz, {}", mode.label()));
+        dialog.set_transient_for(Some(parent));
+        dialog.set_modal(true);
+        dialog.set_default_size(420, 320);
+
+        let progress = Label::new(None);
+        let prompt = Label::new(None);
+        prompt.set_selectable(true);
+        prompt.set_line_wrap(true);
+        prompt.set_vexpand(true);
+
+        let choices = Box::new(Orientation::Vertical, 6);
+        let answer_box = Box::new(Orientation::Horizontal, 6);
+        let answer = Entry::new();
+        answer.set_hexpand(true);
+        answer.set_placeholder_text("Translation");
+        let check = Button::new_with_mnemonic("_Check");
+        check.set_tooltip_text("Check the answer. Enter");
+        answer_box.add(&answer);
+        answer_box.add(&check);
+
+        let feedback = Label::new(None);
+        feedback.set_line_wrap(true);
+        let next = Button::new_with_mnemonic("_Next");
+        next.set_tooltip_text("Next question. Enter");
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(6);
+        content_area.add(&progress);
+        content_area.add(&prompt);
+        content_area.add(&choices);
+        content_area.add(&answer_box);
+        content_area.add(&feedback);
+        content_area.add(&next);
+
+        let quiz = QuizDialog {
+            dialog,
+            progress,
+            prompt,
+            choices,
+            answer_box,
+            answer,
+            check,
+            feedback,
+            next,
+            questions: Rc::new(questions),
+            current: Rc::new(AtomicUsize::new(0)),
+            right_answers: Rc::new(AtomicUsize::new(0)),
+            answered: Rc::new(AtomicBool::new(false)),
+            on_answer,
+        };
+
+        let quiz_clonned = quiz.clone();
+        quiz.check.connect_clicked(move |_| quiz_clonned.check_typed());
+        let quiz_clonned = quiz.clone();
+        quiz.answer.connect_activate(move |_| quiz_clonned.check_typed());
+        let quiz_clonned = quiz.clone();
+        quiz.next.connect_clicked(move |_| quiz_clonned.next_question());
+        // 1 to 4 choose the answer of a multiple-choice question
+        let quiz_clonned = quiz.clone();
+        quiz.dialog.connect_key_press_event(move |_, event| {
+            if quiz_clonned.answer.has_focus() {
+                return Inhibit(false);
+            }
+            let digit = gdk::keyval_to_unicode(event.get_keyval()).and_then(|c| c.to_digit(10));
+            match digit {
+                Some(digit) if digit >= 1 && digit as usize <= CHOICES => {
+                    quiz_clonned.choose(digit as usize - 1);
+                    Inhibit(true)
+                }
+                _ => Inhibit(false),
+            }
+        });
+        quiz.dialog.connect_response(|dialog, _| dialog.destroy());
+        quiz
+    }
+
+    pub fn show(&self) {
+        self.dialog.show_all();
+        self.show_question();
+    }
+
+    /// Show the current question, or the score at the end of the quiz
+    fn show_question(&self) {
+        let current = self.current.load(Ordering::SeqCst);
+        let right_answers = self.right_answers.load(Ordering::SeqCst);
+        self.answered.store(false, Ordering::SeqCst);
+        self.feedback.set_text("");
+        self.next.set_sensitive(false);
+        for child in self.choices.get_children() {
+            self.choices.remove(&child);
+        }
+        let question = match self.questions.get(current) {
+            Some(question) => question,
+            None => {
+                self.show_score();
+                return;
+            }
+        };
+        self.progress.set_text(&format!(
+            "Question {} of {}, {} right",
+            current + 1,
+            self.questions.len(),
+            right_answers
+        ));
+        self.prompt.set_markup(&format!(
+            "<span size=\"x-large\" weight=\"bold\">{}</span>",
+            escape(&question.prompt)
+        ));
+        if question.choices.is_empty() {
+            self.choices.hide();
+            self.answer_box.show();
+            self.answer.set_text("");
+            self.answer.set_sensitive(true);
+            self.check.set_sensitive(true);
+            self.answer.grab_focus();
+        } else {
+            self.answer_box.hide();
+            for (i, choice) in question.choices.iter().enumerate() {
+                let button = Button::new_with_label(&format!("{}. {}", i + 1, choice));
+                let quiz_clonned = self.clone();
+                button.connect_clicked(move |_| quiz_clonned.choose(i));
+                self.choices.add(&button);
+            }
+            self.choices.show_all();
+            if let Some(first) = self.choices.get_children().first() {
+                first.grab_focus();
+            }
+        }
+    }
+
+    /// Answer the current multiple-choice question with the choice at `index`
+    fn choose(&self, index: usize) {
+        if self.answered.load(Ordering::SeqCst) {
+            return;
+        }
+        let right = match self.questions.get(self.current.load(Ordering::SeqCst)) {
+            Some(question) if index < question.choices.len() => index == question.right_choice,
+            _ => return,
+        };
+        for (i, button) in self.choices.get_children().iter().enumerate() {
+            button.set_sensitive(i == index);
+        }
+        self.answer_with(right);
+    }
+
+    /// Check the typed answer of the current question
+    fn check_typed(&self) {
+        let right = match self.questions.get(self.current.load(Ordering::SeqCst)) {
+            Some(question) if question.choices.is_empty() => {
+                question.is_right_answer(&self.answer.get_text().unwrap_or_default())
+            }
+            _ => return,
+        };
+        self.answer.set_sensitive(false);
+        self.check.set_sensitive(false);
+        self.answer_with(right);
+    }
+
+    /// Record the answer of the current question, once, and tell whether it is right
+    fn answer_with(&self, right: bool) {
+        if self.answered.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        let question = match self.questions.get(self.current.load(Ordering::SeqCst)) {
+            Some(question) => question,
+            None => return,
+        };
+        (self.on_answer)(&question.word, right);
+        if right {
+            self.right_answers.fetch_add(1, Ordering::SeqCst);
+            self.feedback.set_markup("<b>Right!</b>");
+        } else {
+            self.feedback.set_markup(&format!(
+                "<b>Wrong,</b> the answer is {}",
+                escape(question.right_answer())
+            ));
+        }
+        self.next.set_sensitive(true);
+        self.next.grab_focus();
+    }
+
+    fn next_question(&self) {
+        if self.answered.load(Ordering::SeqCst) {
+            self.current.fetch_add(1, Ordering::SeqCst);
+            self.show_question();
+        }
+    }
+
+    /// Show the score of the session, once all questions are answered
+    fn show_score(&self) {
+        let right_answers = self.right_answers.load(Ordering::SeqCst);
+        let total = self.questions.len();
+        self.choices.hide();
+        self.answer_box.hide();
+        self.next.hide();
+        self.progress.set_text("Done!");
+        let percent = if total > 0 { right_answers * 100 / total } else { 0 };
+        self.prompt.set_markup(&format!(
+            "<span size=\"x-large\" weight=\"bold\">Score: {} of {} ({}%)</span>",
+            right_answers, total, percent
+        ));
+    }
+}
+
+/// `text` escaped for the Pango markup
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
@@ -0,0 +1,169 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Formatted entries in the translation pane, see `rbedic::Entry`
+
+use glib::ObjectExt;
+use gtk::*;
+use rbedic::{entry, Entry};
+
+const TAG_HEADWORD: &str = "headword";
+const TAG_TRANSCRIPTION: &str = "transcription";
+const TAG_POS: &str = "pos";
+const TAG_SENSE: &str = "sense";
+const TAG_NUMBER: &str = "number";
+const TAG_EXAMPLE: &str = "example";
+/// Words which are headwords themselves, see `link_at()`
+const TAG_LINK: &str = "link";
+/// Personal note of the user, shown after the entry
+const TAG_NOTE: &str = "note";
+
+/// Indentation of the senses and of their examples, in pixels
+const SENSE_MARGIN: i32 = 20;
+const EXAMPLE_MARGIN: i32 = 40;
+
+/// Add the tags used by `render_entry()` to the tag table of `buff`
+pub fn create_tags(buff: &TextBuffer) {
+    let tag_table = match buff.get_tag_table() {
+        Some(tag_table) => tag_table,
+        None => return,
+    };
+    let tags: [(&str, &[(&str, &dyn ToValue)]); 8] = [
+        (TAG_HEADWORD, &[("font", &"Bold"), ("scale", &1.2f64)]),
+        (TAG_TRANSCRIPTION, &[("font", &"Italic")]),
+        (TAG_POS, &[("font", &"Bold Italic"), ("foreground", &"#3465a4")]),
+        (TAG_SENSE, &[("left-margin", &SENSE_MARGIN)]),
+        (TAG_NUMBER, &[("font", &"Bold")]),
+        (TAG_EXAMPLE, &[("left-margin", &EXAMPLE_MARGIN), ("foreground", &"#888a85")]),
+        (TAG_LINK, &[("foreground", &"#204a87"), ("font", &"Bold")]),
+        (TAG_NOTE, &[("font", &"Italic"), ("foreground", &"#5c3566"), ("pixels-above-lines", &8i32)]),
+    ];
+    for &(name, properties) in tags.iter() {
+        let tag = TextTag::new(Some(name));
+        for &(property, value) in properties {
+            if let Err(err) = tag.set_property(property, value) {
+                warn!("Can not set {} of text tag {}: {:?}", property, name, err);
+            }
+        }
+        tag_table.add(&tag);
+    }
+}
+
+/// Show the entry `translation` in `buff`: the headword, the transcription
+/// and the senses grouped by part of speech. Entries which can not be parsed
+/// are shown as plain text. The glosses for which `is_headword` is true
+/// and the references to other words become links.
+pub fn render_entry(buff: &TextBuffer, translation: &str, is_headword: &dyn Fn(&str) -> bool) {
+    let entry = Entry::parse(translation);
+    if entry.is_structured() {
+        render_senses(buff, &entry, is_headword);
+    } else {
+        buff.set_text(translation);
+    }
+    link_references(buff, &entry.headword);
+}
+
+fn render_senses(buff: &TextBuffer, entry: &Entry, is_headword: &dyn Fn(&str) -> bool) {
+    buff.set_text("");
+    insert(buff, &entry.headword, &[TAG_HEADWORD]);
+    if let Some(ref transcription) = entry.transcription {
+        insert(buff, "  ", &[]);
+        insert(buff, &format!("[{}]", transcription), &[TAG_TRANSCRIPTION]);
+    }
+    insert(buff, "\n", &[]);
+    let mut pos: Option<&str> = None;
+    let mut number = 0;
+    for (i, sense) in entry.senses.iter().enumerate() {
+        let sense_pos = sense.pos.as_ref().map(|pos| pos.as_str());
+        if i == 0 || sense_pos != pos {
+            pos = sense_pos;
+            number = 0;
+            if let Some(pos) = pos {
+                insert(buff, &format!("{}\n", pos), &[TAG_POS]);
+            }
+        }
+        number += 1;
+        // Senses are numbered when their group has more than one
+        let numbered = number > 1
+            || entry
+                .senses
+                .get(i + 1)
+                .map_or(false, |next| next.pos.as_ref().map(|pos| pos.as_str()) == pos);
+        if numbered {
+            insert(buff, &format!("{}. ", number), &[TAG_SENSE, TAG_NUMBER]);
+        }
+        for (j, gloss) in sense.glosses.iter().enumerate() {
+            if j > 0 {
+                insert(buff, ", ", &[TAG_SENSE]);
+            }
+            let word = entry::gloss_word(gloss);
+            if word.start < word.end && is_headword(&gloss[word.clone()]) {
+                insert(buff, &gloss[..word.start], &[TAG_SENSE]);
+                insert(buff, &gloss[word.clone()], &[TAG_SENSE, TAG_LINK]);
+                insert(buff, &gloss[word.end..], &[TAG_SENSE]);
+            } else {
+                insert(buff, gloss, &[TAG_SENSE]);
+            }
+        }
+        insert(buff, "\n", &[TAG_SENSE]);
+        for example in &sense.examples {
+            insert(buff, &format!("{}\n", example), &[TAG_EXAMPLE]);
+        }
+    }
+}
+
+/// Append the personal `note` of the user to the entry shown in `buff`
+pub fn render_note(buff: &TextBuffer, note: &str) {
+    if !buff.get_end_iter().starts_line() {
+        insert(buff, "\n", &[]);
+    }
+    insert(buff, &format!("Note: {}", note.trim_end()), &[TAG_NOTE]);
+}
+
+/// Append `text` to `buff`, with the tags named `tags`
+fn insert(buff: &TextBuffer, text: &str, tags: &[&str]) {
+    let start_offset = buff.get_end_iter().get_offset();
+    buff.insert(&mut buff.get_end_iter(), text);
+    let start = buff.get_iter_at_offset(start_offset);
+    let end = buff.get_end_iter();
+    for tag in tags {
+        buff.apply_tag_by_name(tag, &start, &end);
+    }
+}
+
+/// Make links of the words referred to by `see WORD` and alike, see `entry::references()`
+fn link_references(buff: &TextBuffer, headword: &str) {
+    let text = match buff.get_text(&buff.get_start_iter(), &buff.get_end_iter(), false) {
+        Some(text) => text,
+        None => return,
+    };
+    for reference in entry::references(&text) {
+        if text[reference.clone()].to_uppercase() == headword.to_uppercase() {
+            continue;
+        }
+        // Text iterators count characters, not bytes
+        let start = buff.get_iter_at_offset(text[..reference.start].chars().count() as i32);
+        let end = buff.get_iter_at_offset(text[..reference.end].chars().count() as i32);
+        buff.apply_tag_by_name(TAG_LINK, &start, &end);
+    }
+}
+
+/// The word of the link at `iter`, if any
+pub fn link_at(buff: &TextBuffer, iter: &TextIter) -> Option<String> {
+    let tag = buff.get_tag_table()?.lookup(TAG_LINK)?;
+    if !iter.has_tag(&tag) {
+        return None;
+    }
+    let mut start = iter.clone();
+    if !start.begins_tag(Some(&tag)) {
+        start.backward_to_tag_toggle(Some(&tag));
+    }
+    let mut end = iter.clone();
+    end.forward_to_tag_toggle(Some(&tag));
+    buff.get_text(&start, &end, false)
+}
@@ -0,0 +1,189 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Flashcards of the history words, see `rbedic::review`
+
+use gdk;
+use gdk::enums::key;
+use gtk::*;
+use rbedic::review::{Grade, GRADES};
+use rbedic::DictDB;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use super::render;
+
+/// Dialog showing the headword of each due word, then its translation on demand,
+/// and asking how well it was remembered
+#[derive(Clone)]
+pub struct ReviewDialog {
+    pub dialog: Dialog,
+    progress: Label,
+    headword: Label,
+    buff: TextBuffer,
+    show_answer: Button,
+    grade_buttons: Box,
+    /// Words left to review, the current one first
+    queue: Rc<Mutex<VecDeque<DictDB>>>,
+    answered: Rc<AtomicUsize>,
+    /// True when the translation of the current word is shown
+    revealed: Rc<AtomicBool>,
+}
+
+impl ReviewDialog {
+    /// A dialog reviewing `words`. `on_answer` records each answer; words answered
+    /// with `Grade::Again` are asked again at the end.
+    pub fn new(parent: &Window, words: Vec<DictDB>, on_answer: Rc<dyn Fn(&DictDB, Grade)>) -> ReviewDialog {
+        let dialog = Dialog::new();
+        dialog.set_title("RBEdic: Review");
+        dialog.set_transient_for(Some(parent));
+        dialog.set_modal(true);
+        dialog.set_default_size(420, 320);
+
+        let progress = Label::new(None);
+        let headword = Label::new(None);
+        headword.set_selectable(true);
+
+        let buff = TextBuffer::new(None);
+        render::create_tags(&buff);
+        let text_view = TextView::new_with_buffer(&buff);
+        text_view.set_editable(false);
+        text_view.set_cursor_visible(false);
+        text_view.set_wrap_mode(WrapMode::Word);
+        text_view.set_left_margin(10);
+        text_view.set_right_margin(10);
+        let scrolled = ScrolledWindow::new(None, None);
+        scrolled.add(&text_view);
+        scrolled.set_vexpand(true);
+
+        let show_answer = Button::new_with_mnemonic("_Show answer");
+        show_answer.set_tooltip_text("Show the translation. Space");
+        let grade_buttons = Box::new(Orientation::Horizontal, 6);
+        grade_buttons.set_homogeneous(true);
+
+        let content_area = dialog.get_content_area();
+        content_area.set_spacing(6);
+        content_area.add(&progress);
+        content_area.add(&headword);
+        content_area.add(&scrolled);
+        content_area.add(&show_answer);
+        content_area.add(&grade_buttons);
+
+        let review = ReviewDialog {
+            dialog,
+            progress,
+            headword,
+            buff,
+            show_answer,
+            grade_buttons,
+            queue: Rc::new(Mutex::new(words.into_iter().collect())),
+            answered: Rc::new(AtomicUsize::new(0)),
+            revealed: Rc::new(AtomicBool::new(false)),
+        };
+
+        for (i, &grade) in GRADES.iter().enumerate() {
+            let button = Button::new_with_label(&format!("{} ({})", grade.label(), i + 1));
+            let review_clonned = review.clone();
+            let on_answer = on_answer.clone();
+            button.connect_clicked(move |_| review_clonned.answer(grade, &*on_answer));
+            review.grade_buttons.add(&button);
+        }
+        let review_clonned = review.clone();
+        review
+            .show_answer
+            .connect_clicked(move |_| review_clonned.reveal());
+        // Space or Enter shows the answer, 1 to 4 grade it
+        let review_clonned = review.clone();
+        review.dialog.connect_key_press_event(move |_, event| {
+            let keyval = event.get_keyval();
+            if keyval == key::space || keyval == key::Return {
+                review_clonned.reveal();
+                return Inhibit(true);
+            }
+            let digit = gdk::keyval_to_unicode(keyval).and_then(|c| c.to_digit(10));
+            match digit {
+                Some(digit) if digit >= 1 && digit as usize <= GRADES.len() => {
+                    review_clonned.answer(GRADES[digit as usize - 1], &*on_answer);
+                    Inhibit(true)
+                }
+                _ => Inhibit(false),
+            }
+        });
+        review.dialog.connect_response(|dialog, _| dialog.destroy());
+        review
+    }
+
+    pub fn show(&self) {
+        self.dialog.show_all();
+        self.show_card();
+    }
+
+    /// Show the headword of the current word, or the end of the review
+    fn show_card(&self) {
+        let queue = self.queue.lock().unwrap();
+        let answered = self.answered.load(Ordering::SeqCst);
+        self.revealed.store(false, Ordering::SeqCst);
+        self.buff.set_text("");
+        self.grade_buttons.set_sensitive(false);
+        match queue.front() {
+            Some(word) => {
+                let escaped = word
+                    .word
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;");
+                self.headword
+                    .set_markup(&format!("<span size=\"x-large\" weight=\"bold\">{}</span>", escaped));
+                self.progress
+                    .set_text(&format!("{} answered, {} left", answered, queue.len()));
+                self.show_answer.set_sensitive(true);
+                self.show_answer.grab_focus();
+            }
+            None => {
+                self.headword.set_text("Done!");
+                self.progress
+                    .set_text(&format!("{} answered, no more words are due today", answered));
+                self.show_answer.set_sensitive(false);
+            }
+        }
+    }
+
+    /// Show the translation of the current word
+    fn reveal(&self) {
+        let queue = self.queue.lock().unwrap();
+        if let Some(word) = queue.front() {
+            self.revealed.store(true, Ordering::SeqCst);
+            render::render_entry(&self.buff, &word.translation, &|_| false);
+            self.show_answer.set_sensitive(false);
+            self.grade_buttons.set_sensitive(true);
+        }
+    }
+
+    /// Record `grade` for the current word, once its translation is shown
+    fn answer(&self, grade: Grade, on_answer: &dyn Fn(&DictDB, Grade)) {
+        if !self.revealed.load(Ordering::SeqCst) {
+            return;
+        }
+        let word = {
+            let mut queue = self.queue.lock().unwrap();
+            let word = match queue.pop_front() {
+                Some(word) => word,
+                None => return,
+            };
+            if grade == Grade::Again {
+                queue.push_back(word.clone());
+            }
+            word
+        };
+        on_answer(&word, grade);
+        self.answered.fetch_add(1, Ordering::SeqCst);
+        self.show_card();
+    }
+}
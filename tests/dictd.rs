@@ -0,0 +1,238 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Integration tests of the DICT server, against a server on an ephemeral port.
+
+extern crate rbedic;
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use rbedic::dictd::DictServer;
+use rbedic::{Dictionaries, Dictionary, Direction};
+
+const EN_BG: &str = "ABANDON
+[@b'and@n] v 1. изоставям, напускам
+2. отказвам се от
+^;ABANDONED
+a изоставен
+^;ABC
+n азбука
+^;BOOK
+n книга
+^;BOOK
+v резервирам
+^;";
+
+const BG_EN: &str = "КНИГА
+ж book
+^;МАМА
+ж mother, mum
+^;";
+
+/// Start a server with the test dictionaries on a free port of the loopback interface
+fn start_server(limit: usize) -> SocketAddr {
+    let dictionaries = Dictionaries {
+        en_bg: Dictionary::new(Direction::EnBg, EN_BG),
+        bg_en: Dictionary::new(Direction::BgEn, BG_EN),
+        extra: Vec::new(),
+    };
+    let server = DictServer::bind("127.0.0.1:0", Arc::new(dictionaries), limit).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+/// A connection to the server, past the banner
+struct Client {
+    reader: BufReader<TcpStream>,
+    writer: TcpStream,
+    banner: String,
+}
+
+impl Client {
+    fn connect(addr: SocketAddr) -> Client {
+        let writer = TcpStream::connect(addr).unwrap();
+        let mut reader = BufReader::new(writer.try_clone().unwrap());
+        let mut banner = String::new();
+        reader.read_line(&mut banner).unwrap();
+        Client {
+            reader,
+            writer,
+            banner,
+        }
+    }
+
+    fn line(&mut self) -> String {
+        let mut line = String::new();
+        self.reader.read_line(&mut line).unwrap();
+        assert!(line.ends_with("\r\n"), "{:?}", line);
+        line.trim_end_matches("\r\n").to_string()
+    }
+
+    /// Lines of a text, up to the line with a single dot
+    fn text(&mut self) -> Vec<String> {
+        let mut lines = Vec::new();
+        loop {
+            let line = self.line();
+            if line == "." {
+                return lines;
+            }
+            lines.push(line);
+        }
+    }
+
+    /// Send `command` and return its first status line
+    fn send(&mut self, command: &str) -> String {
+        write!(self.writer, "{}\r\n", command).unwrap();
+        self.line()
+    }
+
+    /// Send a MATCH command and return the matched words, `database word` each
+    fn match_words(&mut self, database: &str, strategy: &str, word: &str) -> Vec<String> {
+        let status = self.send(&format!("MATCH {} {} \"{}\"", database, strategy, word));
+        if status.starts_with("552 ") {
+            return Vec::new();
+        }
+        assert!(status.starts_with("152 "), "{}", status);
+        let words = self.text();
+        assert_eq!(self.line(), "250 ok");
+        words
+    }
+}
+
+#[test]
+fn banner() {
+    let addr = start_server(100);
+    let client = Client::connect(addr);
+    assert!(client.banner.starts_with("220 "), "{}", client.banner);
+    assert!(client.banner.contains("<>"), "{}", client.banner);
+}
+
+#[test]
+fn define_found() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("DEFINE en_bg abandon"), "150 1 definitions retrieved");
+    assert_eq!(client.line(), "151 \"ABANDON\" en_bg \"English-Bulgarian\"");
+    let text = client.text();
+    assert_eq!(text[0], "ABANDON");
+    assert!(text[1].contains("изоставям"));
+    assert_eq!(client.line(), "250 ok");
+}
+
+#[test]
+fn define_homonyms_of_all_databases() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("DEFINE * book"), "150 2 definitions retrieved");
+    for translation in &["n книга", "v резервирам"] {
+        assert_eq!(client.line(), "151 \"BOOK\" en_bg \"English-Bulgarian\"");
+        assert_eq!(client.text(), vec!["BOOK".to_string(), translation.to_string()]);
+    }
+    assert_eq!(client.line(), "250 ok");
+}
+
+#[test]
+fn define_not_found() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("DEFINE en_bg aban"), "552 no match");
+    assert_eq!(client.send("DEFINE bg_en book"), "552 no match");
+    assert!(client.send("DEFINE de_en book").starts_with("550 "));
+}
+
+#[test]
+fn match_strategies() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.match_words("en_bg", "exact", "book"), vec!["en_bg \"BOOK\""]);
+    assert_eq!(
+        client.match_words("en_bg", "prefix", "ab"),
+        vec!["en_bg \"ABANDON\"", "en_bg \"ABANDONED\"", "en_bg \"ABC\""]
+    );
+    assert_eq!(client.match_words("*", ".", "кн"), vec!["bg_en \"КНИГА\""]);
+    assert_eq!(
+        client.match_words("en_bg", "re", "^ab.*d$"),
+        vec!["en_bg \"ABANDONED\""]
+    );
+    assert_eq!(client.match_words("en_bg", "lev", "bokk"), vec!["en_bg \"BOOK\""]);
+    assert_eq!(client.match_words("en_bg", "prefix", "zebra"), Vec::<String>::new());
+    assert!(client.send("MATCH en_bg soundex book").starts_with("551 "));
+}
+
+#[test]
+fn match_limit() {
+    let addr = start_server(2);
+    let mut client = Client::connect(addr);
+    assert_eq!(
+        client.match_words("en_bg", "prefix", ""),
+        vec!["en_bg \"ABANDON\"", "en_bg \"ABANDONED\""]
+    );
+    assert_eq!(client.match_words("*", "re", "a"), vec!["en_bg \"ABANDON\"", "en_bg \"ABANDONED\""]);
+}
+
+#[test]
+fn show_databases_and_strategies() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("SHOW DB"), "110 2 databases present");
+    assert_eq!(
+        client.text(),
+        vec!["en_bg \"English-Bulgarian\"", "bg_en \"Bulgarian-English\""]
+    );
+    assert_eq!(client.line(), "250 ok");
+    assert_eq!(client.send("SHOW STRAT"), "111 4 strategies present");
+    let strategies: Vec<String> = client
+        .text()
+        .iter()
+        .map(|line| line.split_whitespace().next().unwrap().to_string())
+        .collect();
+    assert_eq!(strategies, vec!["exact", "prefix", "re", "lev"]);
+    assert_eq!(client.line(), "250 ok");
+}
+
+#[test]
+fn status_codes() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    assert_eq!(client.send("FOO"), "500 unknown command");
+    assert!(client.send("DEFINE en_bg").starts_with("501 "));
+    assert!(client.send("AUTH user secret").starts_with("502 "));
+    assert_eq!(client.send("QUIT"), "221 bye");
+    // The server closes the connection after QUIT
+    let mut rest = String::new();
+    assert_eq!(client.reader.read_line(&mut rest).unwrap(), 0);
+}
+
+#[test]
+fn connection_limit() {
+    let addr = start_server(100);
+    let clients: Vec<Client> = (0..rbedic::dictd::MAX_CONNECTIONS)
+        .map(|_| Client::connect(addr))
+        .collect();
+    assert!(clients.iter().all(|client| client.banner.starts_with("220 ")));
+    let refused = Client::connect(addr);
+    assert_eq!(refused.banner, "420 server temporarily unavailable\r\n");
+    drop(clients);
+}
+
+#[test]
+fn line_length_limit() {
+    let addr = start_server(100);
+    let mut client = Client::connect(addr);
+    // The longest line: the command and CRLF take MAX_LINE_LEN bytes
+    let longest = format!("DEFINE en_bg {}", "x".repeat(rbedic::dictd::MAX_LINE_LEN - 15));
+    assert_eq!(client.send(&longest), "552 no match");
+    let too_long = format!("DEFINE en_bg {}", "x".repeat(rbedic::dictd::MAX_LINE_LEN));
+    assert_eq!(client.send(&too_long), "500 line too long");
+    // The connection is still usable
+    assert!(client.send("DEFINE en_bg book").starts_with("150 "));
+}
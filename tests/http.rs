@@ -0,0 +1,242 @@
+// Copyright 2018 Ivan Dzhonev
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Integration tests of the HTTP lookup API, against a server on an ephemeral port.
+
+extern crate rbedic;
+extern crate serde_json;
+
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
+use std::thread;
+
+use rbedic::http::HttpServer;
+use rbedic::{Dictionaries, Dictionary, Direction};
+use serde_json::Value;
+
+const EN_BG: &str = "ABANDON
+[@b'and@n] v 1. изоставям, напускам
+2. отказвам се от
+^;ABANDONED
+a изоставен
+^;ABC
+n азбука
+^;BOOK
+n книга
+^;";
+
+const BG_EN: &str = "КНИГА
+ж book
+^;МАМА
+ж mother, mum
+^;";
+
+/// Start a server with the test dictionaries on a free port of the loopback interface
+fn start_server(limit: usize) -> SocketAddr {
+    start_server_with_cors(limit, false)
+}
+
+fn start_server_with_cors(limit: usize, cors: bool) -> SocketAddr {
+    let dictionaries = Dictionaries {
+        en_bg: Dictionary::new(Direction::EnBg, EN_BG),
+        bg_en: Dictionary::new(Direction::BgEn, BG_EN),
+        extra: Vec::new(),
+    };
+    let server = HttpServer::bind("127.0.0.1:0", Arc::new(dictionaries), limit, cors).unwrap();
+    let addr = server.local_addr().unwrap();
+    thread::spawn(move || server.run());
+    addr
+}
+
+/// Send `GET target` and return the head and the body of the response
+fn request(addr: SocketAddr, target: &str) -> (String, String) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(
+        stream,
+        "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        target
+    ).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    let (head, body) = response.split_once("\r\n\r\n").unwrap();
+    (head.to_string(), body.to_string())
+}
+
+/// Send `GET target` and return the status code and the JSON body
+fn get(addr: SocketAddr, target: &str) -> (u16, Value) {
+    let (head, body) = request(addr, target);
+    assert!(head.contains("Content-Type: application/json"), "{}", head);
+    let status = head.split_whitespace().nth(1).unwrap().parse().unwrap();
+    (status, serde_json::from_str(&body).unwrap())
+}
+
+#[test]
+fn binds_to_loopback() {
+    let addr = start_server(100);
+    assert!(addr.ip().is_loopback());
+    assert_ne!(addr.port(), 0);
+}
+
+#[test]
+fn lookup_exact_word() {
+    let addr = start_server(100);
+    let (status, json) = get(addr, "/lookup?q=abandon&dir=en-bg");
+    assert_eq!(status, 200);
+    assert_eq!(json["exact"], true);
+    assert_eq!(json["direction"], "en-bg");
+    assert_eq!(json["total"], 2);
+    let entry = &json["entries"][0];
+    assert_eq!(entry["headword"], "ABANDON");
+    assert_eq!(entry["direction"], "en-bg");
+    assert!(entry["translation"].as_str().unwrap().contains("изоставям"));
+    assert_eq!(json["entries"][1]["headword"], "ABANDONED");
+}
+
+#[test]
+fn lookup_prefix_with_limit() {
+    let addr = start_server(100);
+    let (status, json) = get(addr, "/lookup?q=ab&limit=1");
+    assert_eq!(status, 200);
+    assert_eq!(json["exact"], false);
+    assert_eq!(json["total"], 3);
+    assert_eq!(json["entries"].as_array().unwrap().len(), 1);
+}
+
+#[test]
+fn lookup_without_limit() {
+    let addr = start_server(1);
+    let (_, json) = get(addr, "/lookup?q=a&limit=0");
+    assert_eq!(json["total"], 3);
+    assert_eq!(json["entries"].as_array().unwrap().len(), 3);
+}
+
+#[test]
+fn lookup_default_limit() {
+    let addr = start_server(2);
+    let (_, json) = get(addr, "/lookup?q=a");
+    assert_eq!(json["total"], 3);
+    assert_eq!(json["entries"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn lookup_detects_direction() {
+    let addr = start_server(100);
+    // мама
+    let (status, json) = get(addr, "/lookup?q=%D0%BC%D0%B0%D0%BC%D0%B0");
+    assert_eq!(status, 200);
+    assert_eq!(json["direction"], "bg-en");
+    assert_eq!(json["exact"], true);
+    assert_eq!(json["entries"][0]["headword"], "МАМА");
+}
+
+#[test]
+fn lookup_without_matches() {
+    let addr = start_server(100);
+    let (status, json) = get(addr, "/lookup?q=zebra");
+    assert_eq!(status, 200);
+    assert_eq!(json["total"], 0);
+    assert_eq!(json["entries"].as_array().unwrap().len(), 0);
+}
+
+#[test]
+fn lookup_bad_parameters() {
+    let addr = start_server(100);
+    assert_eq!(get(addr, "/lookup").0, 400);
+    assert_eq!(get(addr, "/lookup?q=").0, 400);
+    assert_eq!(get(addr, "/lookup?q=book&dir=de-en").0, 400);
+    let (status, json) = get(addr, "/lookup?q=book&limit=many");
+    assert_eq!(status, 400);
+    assert!(json["error"].is_string());
+}
+
+#[test]
+fn entry_found() {
+    let addr = start_server(100);
+    let (status, json) = get(addr, "/entry/Book");
+    assert_eq!(status, 200);
+    assert_eq!(json["headword"], "BOOK");
+    assert_eq!(json["direction"], "en-bg");
+    assert_eq!(json["translation"], "BOOK\nn книга");
+}
+
+#[test]
+fn entry_percent_encoded() {
+    let addr = start_server(100);
+    // книга
+    let (status, json) = get(addr, "/entry/%D0%BA%D0%BD%D0%B8%D0%B3%D0%B0");
+    assert_eq!(status, 200);
+    assert_eq!(json["headword"], "КНИГА");
+    assert_eq!(json["direction"], "bg-en");
+}
+
+#[test]
+fn entry_not_found() {
+    let addr = start_server(100);
+    // Only a prefix of ABANDON
+    let (status, json) = get(addr, "/entry/aban");
+    assert_eq!(status, 404);
+    assert!(json["error"].is_string());
+}
+
+#[test]
+fn unknown_path() {
+    let addr = start_server(100);
+    assert_eq!(get(addr, "/translate?q=book").0, 404);
+}
+
+#[test]
+fn cors_header_only_if_enabled() {
+    let (head, _) = request(start_server(100), "/lookup?q=book");
+    assert!(!head.contains("Access-Control-Allow-Origin"), "{}", head);
+    let (head, _) = request(start_server_with_cors(100, true), "/lookup?q=book");
+    assert!(head.contains("Access-Control-Allow-Origin: *"), "{}", head);
+}
+
+#[test]
+fn percent_needs_two_hex_digits() {
+    let addr = start_server(100);
+    // A sign is not a hex digit: "%+1" is "%", a space and "1"
+    let (status, json) = get(addr, "/lookup?q=%+1");
+    assert_eq!(status, 200);
+    assert_eq!(json["query"], "% 1");
+    let (_, json) = get(addr, "/lookup?q=%4");
+    assert_eq!(json["query"], "%4");
+}
+
+#[test]
+fn header_size_limit() {
+    let addr = start_server(100);
+    // Exactly MAX_HEADER_LEN bytes, without the empty line ending the headers
+    let head = "GET /lookup?q=book HTTP/1.1\r\nX-Padding: ";
+    let padding = "x".repeat(rbedic::http::MAX_HEADER_LEN - head.len());
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{}{}", head, padding).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 431 "), "{}", response);
+    // Long headers within the limit are fine
+    let mut stream = TcpStream::connect(addr).unwrap();
+    write!(stream, "{}{}\r\n\r\n", head, &padding[100..]).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 200 "), "{}", response);
+}
+
+#[test]
+fn connection_limit() {
+    let addr = start_server(100);
+    let clients: Vec<TcpStream> = (0..rbedic::http::MAX_CONNECTIONS)
+        .map(|_| TcpStream::connect(addr).unwrap())
+        .collect();
+    let mut refused = TcpStream::connect(addr).unwrap();
+    let mut response = String::new();
+    refused.read_to_string(&mut response).unwrap();
+    assert!(response.starts_with("HTTP/1.1 503 Service Unavailable\r\n"), "{}", response);
+    drop(clients);
+}